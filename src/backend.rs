@@ -3,56 +3,119 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 use crate::cli::{BackendType, Cli};
+use crate::config::Config;
+use crate::qdrant::{CollectionMetadata, SearchParams};
+use crate::store::VectorStore;
 
 /// Trait for LLM backends that provide embeddings and text generation
 #[async_trait]
 pub trait LlmBackend: Send + Sync {
     async fn embed(&self, text: &str) -> Result<Vec<f32>>;
-    async fn generate(&self, prompt: &str, context: &str) -> Result<String>;
+
+    /// Generate a reply to `prompt` under the given system prompt
+    async fn chat(&self, system: &str, prompt: &str) -> Result<Completion>;
+
     fn name(&self) -> &'static str;
+
+    /// Model used for text generation
+    fn model(&self) -> &str;
+
+    /// Model used for embeddings
+    fn embed_model(&self) -> &str;
+
+    /// Context window of the generation model in tokens, if the backend reports it
+    async fn context_length(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Generated text along with the token usage reported by the backend
+pub struct Completion {
+    pub text: String,
+    pub usage: Option<TokenUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct TokenUsage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+}
+
+/// Find the context length of `model` in an OpenAI-style `/models` response.
+///
+/// The field isn't part of the OpenAI schema, so look for the names used by
+/// llama.cpp, vLLM and other compatible servers.
+fn context_length_from_models(models: &serde_json::Value, model: &str) -> Option<usize> {
+    const KEYS: &[&str] = &[
+        "context_length",
+        "max_context_length",
+        "context_window",
+        "max_model_len",
+        "n_ctx",
+        "n_ctx_train",
+    ];
+
+    let entries = models.get("data")?.as_array()?;
+    let entry = entries
+        .iter()
+        .find(|m| m.get("id").and_then(|id| id.as_str()) == Some(model))
+        .or_else(|| (entries.len() == 1).then(|| &entries[0]))?;
+
+    KEYS.iter().find_map(|key| {
+        entry
+            .get(key)
+            .or_else(|| entry.get("meta").and_then(|meta| meta.get(key)))
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+    })
+}
+
+/// Parse a generated list into at most `max` items, one per line, without
+/// bullets or "1." / "1)" numbering
+pub fn parse_list(response: &str, max: usize) -> Vec<String> {
+    fn strip_list_marker(line: &str) -> &str {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix(['-', '*', '•']) {
+            return rest.trim();
+        }
+
+        let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 {
+            if let Some(rest) = line[digits..].strip_prefix(['.', ')']) {
+                return rest.trim();
+            }
+        }
+
+        line
+    }
+
+    response
+        .lines()
+        .map(strip_list_marker)
+        .filter(|line| !line.is_empty())
+        .take(max)
+        .map(|line| line.to_string())
+        .collect()
 }
 
 /// Docker Model Runner backend (default)
-/// Connects via Unix socket to /var/run/docker.sock
+/// Connects to its TCP endpoint on localhost:12434
 pub struct DockerModelRunner {
     gen_model: String,
     embed_model: String,
-    socket_path: String,
 }
 
 impl DockerModelRunner {
     pub fn new(_base_url: Option<String>, gen_model: Option<String>, embed_model: Option<String>) -> Self {
-        // Determine socket path - try Docker Desktop path first, then standard path
-        let socket_path = if std::path::Path::new(&format!(
-            "{}/.docker/run/docker.sock",
-            std::env::var("HOME").unwrap_or_default()
-        ))
-        .exists()
-        {
-            format!(
-                "{}/.docker/run/docker.sock",
-                std::env::var("HOME").unwrap_or_default()
-            )
-        } else {
-            "/var/run/docker.sock".to_string()
-        };
-
         Self {
-            socket_path,
             gen_model: gen_model.unwrap_or_else(|| "ai/gemma3".to_string()),
             embed_model: embed_model.unwrap_or_else(|| "ai/nomic-embed-text-v1.5".to_string()),
         }
     }
 
-    fn create_client(&self) -> Result<reqwest::Client> {
-        // For Unix socket, we need to use hyper with unix socket connector
-        // But reqwest doesn't support unix sockets directly, so we'll fall back to TCP if available
-        // or use a workaround via socat/docker proxy
-        Ok(reqwest::Client::new())
-    }
-
     /// Get the API base URL - tries TCP first (localhost:12434), falls back to explaining socket requirement
     fn get_base_url(&self) -> String {
         // Docker Model Runner exposes on localhost:12434 when TCP is enabled
@@ -83,16 +146,6 @@ impl DockerModelRunner {
                     input: String,
                 }
 
-                #[derive(Deserialize)]
-                struct EmbedResponse {
-                    data: Vec<EmbedData>,
-                }
-
-                #[derive(Deserialize)]
-                struct EmbedData {
-                    embedding: Vec<f32>,
-                }
-
                 let embed_result = client
                     .post(format!("{}/embeddings", base_url))
                     .timeout(std::time::Duration::from_secs(30))
@@ -154,7 +207,7 @@ impl LlmBackend for DockerModelRunner {
             .context("No embedding data returned")
     }
 
-    async fn generate(&self, prompt: &str, context: &str) -> Result<String> {
+    async fn chat(&self, system: &str, prompt: &str) -> Result<Completion> {
         let client = reqwest::Client::new();
         let base_url = self.get_base_url();
 
@@ -174,6 +227,8 @@ impl LlmBackend for DockerModelRunner {
         #[derive(Deserialize)]
         struct ChatResponse {
             choices: Vec<Choice>,
+            #[serde(default)]
+            usage: Option<TokenUsage>,
         }
 
         #[derive(Deserialize)]
@@ -186,12 +241,6 @@ impl LlmBackend for DockerModelRunner {
             content: String,
         }
 
-        let system_prompt = format!(
-            "You are a helpful assistant. Answer the user's question using only the context provided below. \
-            If the context doesn't contain relevant information, say so.\n\nContext:\n{}",
-            context
-        );
-
         let res = client
             .post(format!("{}/chat/completions", base_url))
             .json(&ChatRequest {
@@ -199,7 +248,7 @@ impl LlmBackend for DockerModelRunner {
                 messages: vec![
                     ChatMessage {
                         role: "system".to_string(),
-                        content: system_prompt,
+                        content: system.to_string(),
                     },
                     ChatMessage {
                         role: "user".to_string(),
@@ -214,16 +263,44 @@ impl LlmBackend for DockerModelRunner {
             .await
             .context("Failed to parse generation response from Docker Model Runner")?;
 
-        res.choices
+        let text = res
+            .choices
             .into_iter()
             .next()
             .map(|c| c.message.content)
-            .context("No response generated")
+            .context("No response generated")?;
+
+        Ok(Completion {
+            text,
+            usage: res.usage,
+        })
     }
 
     fn name(&self) -> &'static str {
         "Docker Model Runner"
     }
+
+    fn model(&self) -> &str {
+        &self.gen_model
+    }
+
+    fn embed_model(&self) -> &str {
+        &self.embed_model
+    }
+
+    async fn context_length(&self) -> Option<usize> {
+        let models: serde_json::Value = reqwest::Client::new()
+            .get(format!("{}/models", self.get_base_url()))
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await
+            .ok()?
+            .json()
+            .await
+            .ok()?;
+
+        context_length_from_models(&models, &self.gen_model)
+    }
 }
 
 /// Ollama backend (fallback)
@@ -354,10 +431,11 @@ impl LlmBackend for OllamaBackend {
         Ok(res.embedding)
     }
 
-    async fn generate(&self, prompt: &str, context: &str) -> Result<String> {
+    async fn chat(&self, system: &str, prompt: &str) -> Result<Completion> {
         #[derive(Serialize)]
         struct GenerateRequest {
             model: String,
+            system: String,
             prompt: String,
             stream: bool,
         }
@@ -365,21 +443,17 @@ impl LlmBackend for OllamaBackend {
         #[derive(Deserialize)]
         struct GenerateResponse {
             response: String,
+            prompt_eval_count: Option<usize>,
+            eval_count: Option<usize>,
         }
 
-        let full_prompt = format!(
-            "You are a helpful assistant. Answer the user's question using only the context provided below. \
-            If the context doesn't contain relevant information, say so.\n\n\
-            Context:\n{}\n\nQuestion: {}",
-            context, prompt
-        );
-
         let res = self
             .client
             .post(format!("{}/api/generate", self.base_url))
             .json(&GenerateRequest {
                 model: self.gen_model.clone(),
-                prompt: full_prompt,
+                system: system.to_string(),
+                prompt: prompt.to_string(),
                 stream: false,
             })
             .send()
@@ -388,12 +462,60 @@ impl LlmBackend for OllamaBackend {
             .await
             .context("Failed to parse generation response from Ollama")?;
 
-        Ok(res.response)
+        let usage = match (res.prompt_eval_count, res.eval_count) {
+            (Some(prompt_tokens), Some(completion_tokens)) => Some(TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+            }),
+            _ => None,
+        };
+
+        Ok(Completion {
+            text: res.response,
+            usage,
+        })
     }
 
     fn name(&self) -> &'static str {
         "Ollama"
     }
+
+    fn model(&self) -> &str {
+        &self.gen_model
+    }
+
+    fn embed_model(&self) -> &str {
+        &self.embed_model
+    }
+
+    async fn context_length(&self) -> Option<usize> {
+        #[derive(Deserialize)]
+        struct ShowResponse {
+            #[serde(default)]
+            parameters: String,
+        }
+
+        let res = self
+            .client
+            .post(format!("{}/api/show", self.base_url))
+            .timeout(std::time::Duration::from_secs(5))
+            .json(&serde_json::json!({ "model": self.gen_model }))
+            .send()
+            .await
+            .ok()?
+            .json::<ShowResponse>()
+            .await
+            .ok()?;
+
+        // Ollama truncates prompts at num_ctx, not the model's trained context length
+        res.parameters.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("num_ctx"), Some(value)) => value.parse().ok(),
+                _ => None,
+            }
+        })
+    }
 }
 
 /// OpenAI-compatible backend
@@ -461,7 +583,7 @@ impl LlmBackend for OpenAiBackend {
             .context("No embedding data returned")
     }
 
-    async fn generate(&self, prompt: &str, context: &str) -> Result<String> {
+    async fn chat(&self, system: &str, prompt: &str) -> Result<Completion> {
         #[derive(Serialize)]
         struct ChatRequest {
             model: String,
@@ -477,6 +599,8 @@ impl LlmBackend for OpenAiBackend {
         #[derive(Deserialize)]
         struct ChatResponse {
             choices: Vec<Choice>,
+            #[serde(default)]
+            usage: Option<TokenUsage>,
         }
 
         #[derive(Deserialize)]
@@ -489,12 +613,6 @@ impl LlmBackend for OpenAiBackend {
             content: String,
         }
 
-        let system_prompt = format!(
-            "You are a helpful assistant. Answer the user's question using only the context provided below. \
-            If the context doesn't contain relevant information, say so.\n\nContext:\n{}",
-            context
-        );
-
         let res = self
             .client
             .post(format!("{}/chat/completions", self.base_url))
@@ -504,7 +622,7 @@ impl LlmBackend for OpenAiBackend {
                 messages: vec![
                     ChatMessage {
                         role: "system".to_string(),
-                        content: system_prompt,
+                        content: system.to_string(),
                     },
                     ChatMessage {
                         role: "user".to_string(),
@@ -518,37 +636,218 @@ impl LlmBackend for OpenAiBackend {
             .await
             .context("Failed to parse generation response from OpenAI")?;
 
-        res.choices
+        let text = res
+            .choices
             .into_iter()
             .next()
             .map(|c| c.message.content)
-            .context("No response generated")
+            .context("No response generated")?;
+
+        Ok(Completion {
+            text,
+            usage: res.usage,
+        })
     }
 
     fn name(&self) -> &'static str {
         "OpenAI"
     }
+
+    fn model(&self) -> &str {
+        &self.gen_model
+    }
+
+    fn embed_model(&self) -> &str {
+        &self.embed_model
+    }
+
+    async fn context_length(&self) -> Option<usize> {
+        let models: serde_json::Value = self
+            .client
+            .get(format!("{}/models", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await
+            .ok()?
+            .json()
+            .await
+            .ok()?;
+
+        context_length_from_models(&models, &self.gen_model)
+    }
+}
+
+/// Backends for querying collections that may have been embedded with different models
+pub struct CollectionBackends {
+    default: Box<dyn LlmBackend>,
+    /// Backends for embedding models other than the default one
+    others: HashMap<String, Box<dyn LlmBackend>>,
+    /// What was selected for each collection checked on creation, or why it
+    /// can't be used with the embedding model selected for it
+    checked: HashMap<String, std::result::Result<Selection, String>>,
+    config: Config,
+    /// Embedding model given with --embed-model
+    embed_model: Option<String>,
+}
+
+/// Embedding model and search parameters selected for a collection
+struct Selection {
+    embed_model: String,
+    search: SearchParams,
+}
+
+impl CollectionBackends {
+    /// Create the default backend plus one per other embedding model used by `collections`.
+    ///
+    /// A collection is embedded with the model set for it in the config, else
+    /// `--embed-model`, else the model recorded in its metadata, else the default.
+    pub async fn new<'a>(
+        cli: &Cli,
+        config: &Config,
+        store: &dyn VectorStore,
+        collections: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self> {
+        let mut backends = Self {
+            default: create_backend(cli).await?,
+            others: HashMap::new(),
+            checked: HashMap::new(),
+            config: config.clone(),
+            embed_model: cli.embed_model.clone(),
+        };
+
+        for collection in collections {
+            let metadata = store.get_metadata(collection).await?.unwrap_or_default();
+            let selection = backends.select(collection, &metadata);
+            if let Ok(Selection { embed_model, .. }) = &selection {
+                if backends.backend(embed_model).is_none() {
+                    let backend =
+                        create_backend_with_embed_model(cli, Some(embed_model.clone())).await?;
+                    backends.others.insert(embed_model.clone(), backend);
+                }
+            }
+            backends
+                .checked
+                .insert(collection.to_string(), selection.map_err(|e| e.to_string()));
+        }
+
+        Ok(backends)
+    }
+
+    /// Embedding model and search parameters for a collection with `metadata`;
+    /// fails if the collection was built with a different model than selected
+    fn select(&self, collection: &str, metadata: &CollectionMetadata) -> Result<Selection> {
+        let embed_model = self
+            .config
+            .collection(collection)
+            .and_then(|c| c.embed_model.clone())
+            .or_else(|| self.embed_model.clone())
+            .or_else(|| metadata.embed_model.clone())
+            .unwrap_or_else(|| self.default.embed_model().to_string());
+        metadata.check_embed_model(collection, &embed_model)?;
+
+        let search = self
+            .config
+            .storage(collection)
+            .search_params()
+            .or(&metadata.search);
+        Ok(Selection {
+            embed_model,
+            search,
+        })
+    }
+
+    /// Backend embedding with `embed_model`, if one was created
+    fn backend(&self, embed_model: &str) -> Option<&dyn LlmBackend> {
+        if embed_model == self.default.embed_model() {
+            return Some(self.default.as_ref());
+        }
+        self.others.get(embed_model).map(|backend| backend.as_ref())
+    }
+
+    /// Backend for generation and for collections using the default embedding model
+    pub fn default(&self) -> &dyn LlmBackend {
+        self.default.as_ref()
+    }
+
+    /// Parameters to search `collection` with: those in the config, else those
+    /// recorded with the collection
+    pub fn search_params(&self, collection: &str) -> &SearchParams {
+        static NONE: SearchParams = SearchParams {
+            hnsw_ef: None,
+            rescore: None,
+            oversampling: None,
+        };
+        match self.checked.get(collection) {
+            Some(Ok(selection)) => &selection.search,
+            _ => &NONE,
+        }
+    }
+
+    /// Backend embedding with the model `collection` was built with; fails
+    /// if the collection was built with a different model than selected, or
+    /// wasn't among the collections checked on creation
+    pub fn for_collection(&self, collection: &str) -> Result<&dyn LlmBackend> {
+        match self.checked.get(collection) {
+            Some(Ok(selection)) => self.backend(&selection.embed_model).with_context(|| {
+                format!("No backend for embedding model '{}'", selection.embed_model)
+            }),
+            Some(Err(mismatch)) => anyhow::bail!("{}", mismatch),
+            None => anyhow::bail!(
+                "Collection '{}' wasn't checked against the embedding model it was built with",
+                collection
+            ),
+        }
+    }
+
+    /// Backend and search parameters for `collection`, checked against the
+    /// metadata it has now: a server's collections may be created or rebuilt
+    /// with another embedding model after it started
+    pub async fn recheck(
+        &self,
+        store: &dyn VectorStore,
+        collection: &str,
+    ) -> Result<(&dyn LlmBackend, SearchParams)> {
+        let metadata = store.get_metadata(collection).await?.unwrap_or_default();
+        let selection = self.select(collection, &metadata)?;
+        let backend = self.backend(&selection.embed_model).with_context(|| {
+            format!(
+                "Collection '{}' is embedded with '{}', which no backend was created for; \
+                restart to query it",
+                collection, selection.embed_model
+            )
+        })?;
+        Ok((backend, selection.search))
+    }
 }
 
 /// Detect and create the best available backend
 pub async fn create_backend(cli: &Cli) -> Result<Box<dyn LlmBackend>> {
+    create_backend_with_embed_model(cli, cli.embed_model.clone()).await
+}
+
+/// Create a backend like [`create_backend`], but embedding with `embed_model`
+pub async fn create_backend_with_embed_model(
+    cli: &Cli,
+    embed_model: Option<String>,
+) -> Result<Box<dyn LlmBackend>> {
     // If user explicitly specified a backend, use it
     if let Some(ref backend_type) = cli.backend {
         return match backend_type {
             BackendType::Docker => Ok(Box::new(DockerModelRunner::new(
                 cli.base_url.clone(),
                 cli.model.clone(),
-                cli.embed_model.clone(),
+                embed_model.clone(),
             ))),
             BackendType::Ollama => Ok(Box::new(OllamaBackend::new(
                 cli.base_url.clone(),
                 cli.model.clone(),
-                cli.embed_model.clone(),
+                embed_model.clone(),
             ))),
             BackendType::Openai => Ok(Box::new(OpenAiBackend::new(
                 cli.base_url.clone(),
                 cli.model.clone(),
-                cli.embed_model.clone(),
+                embed_model.clone(),
             ))),
         };
     }
@@ -557,7 +856,7 @@ pub async fn create_backend(cli: &Cli) -> Result<Box<dyn LlmBackend>> {
     let docker_runner = DockerModelRunner::new(
         cli.base_url.clone(),
         cli.model.clone(),
-        cli.embed_model.clone(),
+        embed_model.clone(),
     );
     if docker_runner.is_available().await {
         eprintln!("Using Docker Model Runner backend");
@@ -567,7 +866,7 @@ pub async fn create_backend(cli: &Cli) -> Result<Box<dyn LlmBackend>> {
     let ollama = OllamaBackend::new(
         cli.base_url.clone(),
         cli.model.clone(),
-        cli.embed_model.clone(),
+        embed_model.clone(),
     );
     if ollama.is_available().await {
         eprintln!("Using Ollama backend");
@@ -577,7 +876,7 @@ pub async fn create_backend(cli: &Cli) -> Result<Box<dyn LlmBackend>> {
     let openai = OpenAiBackend::new(
         cli.base_url.clone(),
         cli.model.clone(),
-        cli.embed_model.clone(),
+        embed_model.clone(),
     );
     if openai.is_available() {
         eprintln!("Using OpenAI backend");
@@ -585,7 +884,7 @@ pub async fn create_backend(cli: &Cli) -> Result<Box<dyn LlmBackend>> {
     }
 
     // Get info about what's missing for better error message
-    let embed_model = cli.embed_model.clone().unwrap_or_else(|| "ai/nomic-embed-text-v1.5".to_string());
+    let embed_model = embed_model.clone().unwrap_or_else(|| "ai/nomic-embed-text-v1.5".to_string());
     let gen_model = cli.model.clone().unwrap_or_else(|| "ai/gemma3".to_string());
 
     anyhow::bail!(
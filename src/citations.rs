@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::Serialize;
+
+use crate::qdrant::ScoredChunk;
+
+/// A context block cited by the answer
+#[derive(Serialize, Debug, Clone)]
+pub struct Citation {
+    /// The bracketed number used in the answer
+    pub number: usize,
+    pub id: String,
+    pub source: String,
+    /// Collection the chunk was found in, when several were searched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    pub score: f32,
+}
+
+impl Citation {
+    /// One-line description of where the cited text comes from
+    pub fn label(&self) -> String {
+        let mut label = match &self.collection {
+            Some(collection) => format!("[{}] {}", collection, self.source),
+            None => self.source.clone(),
+        };
+        if let Some(page) = self.page {
+            label.push_str(&format!(", page {}", page));
+        }
+        if let Some(heading) = &self.heading {
+            label.push_str(&format!(" > {}", heading));
+        }
+        label
+    }
+}
+
+/// An answer with its citations checked against the context it was given
+pub struct CitedAnswer {
+    /// The answer with citations of blocks that weren't in the context removed
+    pub text: String,
+    /// Cited blocks in number order
+    pub citations: Vec<Citation>,
+    /// Numbers listed alongside a valid citation that don't match any context block
+    pub invalid: Vec<usize>,
+}
+
+/// Parse the inside of a bracket as a citation list such as `1`, `1, 2` or `1,2`
+fn parse_numbers(inner: &str) -> Option<Vec<usize>> {
+    let numbers: Vec<usize> = inner
+        .split(',')
+        .map(|n| n.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    (!numbers.is_empty()).then_some(numbers)
+}
+
+/// Replace every bracketed citation in `text` with what `replace` returns for
+/// its numbers, or remove it (with the spaces before it) when that is `None`.
+///
+/// Only brackets citing at least one of the `blocks` context blocks count as
+/// citations, so numbers such as `[2023]` or `[404]` are left alone.
+fn rewrite(
+    text: &str,
+    blocks: usize,
+    mut replace: impl FnMut(Vec<usize>) -> Option<String>,
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        let Some(close) = rest[open..].find(']').map(|i| open + i) else {
+            break;
+        };
+        // Brackets straight after an identifier are indexing, as in `items[0]`
+        let indexing = rest[..open]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let numbers = parse_numbers(&rest[open + 1..close])
+            .filter(|numbers| !indexing && numbers.iter().any(|n| (1..=blocks).contains(n)));
+        let Some(numbers) = numbers else {
+            // Not a citation; keep the bracket and look for the next one
+            out.push_str(&rest[..open + 1]);
+            rest = &rest[open + 1..];
+            continue;
+        };
+
+        let before = &rest[..open];
+        match replace(numbers) {
+            Some(citation) => {
+                out.push_str(before);
+                out.push_str(&citation);
+            }
+            None => out.push_str(before.trim_end_matches(' ')),
+        }
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Validate the bracketed citations in `answer` against the numbered context
+/// blocks built from `hits`, dropping numbers that don't exist from them
+pub fn resolve(answer: &str, hits: &[ScoredChunk]) -> CitedAnswer {
+    let mut cited: Vec<usize> = Vec::new();
+    let mut invalid: Vec<usize> = Vec::new();
+
+    let text = rewrite(answer, hits.len(), |numbers| {
+        let (valid, bad): (Vec<usize>, Vec<usize>) = numbers
+            .into_iter()
+            .partition(|n| (1..=hits.len()).contains(n));
+
+        for &n in &valid {
+            if !cited.contains(&n) {
+                cited.push(n);
+            }
+        }
+        for n in bad {
+            if !invalid.contains(&n) {
+                invalid.push(n);
+            }
+        }
+
+        let list: Vec<String> = valid.iter().map(|n| n.to_string()).collect();
+        (!list.is_empty()).then(|| format!("[{}]", list.join(", ")))
+    });
+
+    cited.sort_unstable();
+    let citations = cited
+        .into_iter()
+        .map(|number| {
+            let hit = &hits[number - 1];
+            Citation {
+                number,
+                id: hit.chunk.id.clone(),
+                source: hit.chunk.source.clone(),
+                collection: hit.collection.clone(),
+                heading: hit.chunk.heading.clone(),
+                page: hit.chunk.page,
+                score: hit.score,
+            }
+        })
+        .collect();
+
+    CitedAnswer {
+        text,
+        citations,
+        invalid,
+    }
+}
+
+/// Turn `[1, 2]` style citations of the `blocks` context blocks into markdown
+/// footnote references
+pub fn markdown_footnotes(text: &str, blocks: usize) -> String {
+    rewrite(text, blocks, |numbers| {
+        Some(numbers.iter().map(|n| format!("[^{}]", n)).collect())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qdrant::DocumentChunk;
+
+    fn hits(count: usize) -> Vec<ScoredChunk> {
+        (1..=count)
+            .map(|n| ScoredChunk {
+                chunk: DocumentChunk::from_payload(
+                    format!("chunk-{}", n),
+                    serde_json::json!({ "content": "text", "source": format!("doc{}.md", n) }),
+                )
+                .unwrap(),
+                score: 1.0 / n as f32,
+                vector: None,
+                collection: None,
+                fused_score: None,
+            })
+            .collect()
+    }
+
+    fn numbers(answer: &CitedAnswer) -> Vec<usize> {
+        answer.citations.iter().map(|c| c.number).collect()
+    }
+
+    #[test]
+    fn out_of_range_numbers_are_dropped() {
+        let answer = resolve("Paris is the capital [1, 3].", &hits(2));
+        assert_eq!(answer.text, "Paris is the capital [1].");
+        assert_eq!(numbers(&answer), [1]);
+        assert_eq!(answer.invalid, [3]);
+
+        // A bracket citing nothing that exists isn't a citation at all
+        let answer = resolve("See RFC [9110] and [3].", &hits(2));
+        assert_eq!(answer.text, "See RFC [9110] and [3].");
+        assert!(answer.citations.is_empty());
+        assert!(answer.invalid.is_empty());
+    }
+
+    #[test]
+    fn repeated_citations_are_listed_once() {
+        let answer = resolve("First [2]. Second [2, 1]. Third [2].", &hits(2));
+        assert_eq!(answer.text, "First [2]. Second [2, 1]. Third [2].");
+        assert_eq!(numbers(&answer), [1, 2]);
+        assert_eq!(answer.citations[1].source, "doc2.md");
+    }
+
+    #[test]
+    fn adjacent_citations_are_each_resolved() {
+        let answer = resolve("All three agree [1][2][3, 5].", &hits(3));
+        assert_eq!(answer.text, "All three agree [1][2][3].");
+        assert_eq!(numbers(&answer), [1, 2, 3]);
+        assert_eq!(answer.invalid, [5]);
+    }
+}
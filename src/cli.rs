@@ -1,6 +1,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+use crate::grounding::VerifyMode;
+use crate::qdrant::{Condition, QdrantTransport, StorageSettings, WriteOrdering};
+use crate::retrieval::{QueryMode, RetrievalMode, RetrievalOptions};
+use crate::store::StoreType;
 
 #[derive(Parser)]
 #[command(name = "know")]
@@ -51,17 +57,129 @@ pub struct Cli {
     #[arg(long, global = true, env = "KNOW_EMBED_MODEL")]
     pub embed_model: Option<String>,
 
+    /// Where collections are kept: qdrant, local files that need no services, or
+    /// postgres (default: postgres for a postgres:// --store-url, else qdrant)
+    #[arg(long, global = true, value_enum, env = "KNOW_STORE")]
+    pub store: Option<StoreType>,
+
+    /// Directory of the local store (default: ~/.local/share/know on Linux)
+    #[arg(long, global = true, env = "KNOW_STORE_PATH")]
+    pub store_path: Option<PathBuf>,
+
+    /// Connection URL of the PostgreSQL store, e.g. postgres://know@localhost/know
+    #[arg(long, global = true, env = "KNOW_STORE_URL", hide_env_values = true)]
+    pub store_url: Option<String>,
+
     /// Qdrant URL
     #[arg(long, global = true, default_value = "http://localhost:6333", env = "KNOW_QDRANT_URL")]
     pub qdrant_url: String,
 
+    /// API key for secured Qdrant instances, such as Qdrant Cloud
+    #[arg(long, global = true, env = "KNOW_QDRANT_API_KEY", hide_env_values = true)]
+    pub qdrant_api_key: Option<String>,
+
+    /// PEM file of extra CA certificates to trust when connecting to Qdrant over TLS
+    #[arg(long, global = true, env = "KNOW_QDRANT_CA_CERT")]
+    pub qdrant_ca_cert: Option<PathBuf>,
+
+    /// Protocol for writing, searching and scrolling points (grpc needs the grpc feature)
+    #[arg(long, global = true, value_enum, default_value = "rest", env = "KNOW_QDRANT_TRANSPORT")]
+    pub qdrant_transport: QdrantTransport,
+
+    /// Qdrant gRPC URL, used with --qdrant-transport grpc
+    #[arg(long, global = true, default_value = "http://localhost:6334", env = "KNOW_QDRANT_GRPC_URL")]
+    pub qdrant_grpc_url: String,
+
+    /// Timeout for each Qdrant request, in seconds
+    #[arg(long, global = true, default_value = "30", env = "KNOW_QDRANT_TIMEOUT")]
+    pub qdrant_timeout: u64,
+
+    /// How often to retry Qdrant requests that fail with a connection error or a 5xx response
+    #[arg(long, global = true, default_value = "3", env = "KNOW_QDRANT_RETRIES")]
+    pub qdrant_retries: u32,
+
+    /// Maximum points sent to Qdrant per upsert request
+    #[arg(long, global = true, default_value = "64", env = "KNOW_UPSERT_BATCH_SIZE")]
+    pub upsert_batch_size: usize,
+
+    /// Number of upsert requests sent to Qdrant at once
+    #[arg(long, global = true, default_value = "4", env = "KNOW_UPSERT_CONCURRENCY")]
+    pub upsert_concurrency: usize,
+
+    /// Write ordering guarantee for clustered Qdrant deployments
+    #[arg(long, global = true, value_enum, default_value = "weak", env = "KNOW_WRITE_ORDERING")]
+    pub write_ordering: WriteOrdering,
+
+    /// Return from writes once Qdrant has accepted them, without waiting until they are applied
+    #[arg(long, global = true, env = "KNOW_NO_WAIT")]
+    pub no_wait: bool,
+
     /// Docling URL
     #[arg(long, global = true, default_value = "http://localhost:5001", env = "KNOW_DOCLING_URL")]
     pub docling_url: String,
 
-    /// Collection name in qdrant
-    #[arg(long, global = true, default_value = "know", env = "KNOW_COLLECTION")]
-    pub collection: String,
+    /// Collection name in qdrant; run, search and serve accept several
+    /// (repeat the option or separate names with commas)
+    #[arg(
+        long,
+        global = true,
+        default_value = "know",
+        env = "KNOW_COLLECTION",
+        value_delimiter = ','
+    )]
+    pub collection: Vec<String>,
+
+    /// Context window of the generation model in tokens (queried from the backend if unset)
+    #[arg(long, global = true, env = "KNOW_CONTEXT_LENGTH")]
+    pub context_length: Option<usize>,
+
+    /// Tokens of the context window to reserve for the answer
+    #[arg(long, global = true, default_value = "512", env = "KNOW_ANSWER_TOKENS")]
+    pub answer_tokens: usize,
+
+    /// Config file (defaults to know/config.toml in the user config directory)
+    #[arg(long, global = true, env = "KNOW_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Prompt template to answer with: a file, or a name defined in the config.
+    /// With ingest, the template is stored with the collection
+    #[arg(long, global = true, env = "KNOW_PROMPT")]
+    pub prompt: Option<String>,
+
+    /// Answer given instead of calling the model when no hit passes --min-score
+    #[arg(
+        long,
+        global = true,
+        default_value = "I couldn't find anything about that in the knowledge base.",
+        env = "KNOW_NOT_FOUND_MESSAGE"
+    )]
+    pub not_found_message: String,
+
+    /// Print extra diagnostics, such as generated search queries
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+}
+
+impl Cli {
+    /// The collection for commands that work on exactly one
+    pub fn collection(&self) -> anyhow::Result<&str> {
+        match self.collection.as_slice() {
+            [collection] => Ok(collection),
+            _ => anyhow::bail!("This command works on a single collection, not several"),
+        }
+    }
+
+    /// The store to keep collections in, picked from the store URL unless given
+    pub fn store_type(&self) -> StoreType {
+        let postgres_url = self.store_url.as_deref().is_some_and(|url| {
+            url.starts_with("postgres://") || url.starts_with("postgresql://")
+        });
+        match self.store {
+            Some(store) => store,
+            None if postgres_url => StoreType::Postgres,
+            None => StoreType::Qdrant,
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -70,6 +188,33 @@ pub enum Commands {
     Run {
         /// The question you want to ask
         query: Vec<String>,
+
+        /// Output format; json, jsonl and markdown print progress to stderr only
+        #[arg(short, long, value_enum, default_value = "text")]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        verify: VerifyArgs,
+
+        #[command(flatten)]
+        retrieval: RetrievalArgs,
+    },
+
+    /// Show the chunks matching a query, without generating an answer
+    Search {
+        /// The search query
+        query: Vec<String>,
+
+        /// Number of hits to show
+        #[arg(short = 'k', long, default_value = "10")]
+        top_k: usize,
+
+        /// Print hits as JSON
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        retrieval: RetrievalArgs,
     },
 
     /// Ingest files into the knowledge base (services start automatically)
@@ -80,6 +225,19 @@ pub enum Commands {
         /// File extensions to look for (comma-separated: md,txt,pdf,docx)
         #[arg(long, default_value = "md,txt,pdf,docx,html")]
         extensions: String,
+
+        #[command(flatten)]
+        dedup: DedupArgs,
+
+        /// Generate a summary of each document and index it alongside the chunks
+        /// (slower: one generation call per document)
+        #[arg(long)]
+        summarize: bool,
+
+        /// Generate this many questions per chunk and index them as extra search
+        /// vectors for the chunk (slower: one generation call per chunk)
+        #[arg(long, default_value = "0")]
+        questions: usize,
     },
 
     /// Serve an OpenAI-compatible API endpoint (services start automatically)
@@ -87,6 +245,10 @@ pub enum Commands {
         /// Port to serve on
         #[arg(short, long, default_value = "8080")]
         port: u16,
+
+        /// Default minimum similarity score for requests that don't set min_score
+        #[arg(long)]
+        min_score: Option<f32>,
     },
 
     /// Stop qdrant and docling services
@@ -99,6 +261,26 @@ pub enum Commands {
         collection: String,
     },
 
+    /// List, show and remove the documents in a collection
+    Docs {
+        #[command(subcommand)]
+        command: DocsCommand,
+    },
+
+    /// Re-embed a collection with --embed-model (or the backend's default model),
+    /// replacing its vectors
+    Reembed {
+        /// Write the re-embedded points to this new collection and keep the original
+        #[arg(long)]
+        to: Option<String>,
+    },
+
+    /// List, inspect, copy and rename collections, and manage aliases
+    Collections {
+        #[command(subcommand)]
+        command: CollectionsCommand,
+    },
+
     /// Push the vector database to Docker Hub
     Push {
         /// Image name (e.g., myuser/my-knowledge:v1)
@@ -115,6 +297,83 @@ pub enum Commands {
     Status,
 }
 
+#[derive(Subcommand)]
+pub enum DocsCommand {
+    /// List the ingested sources with their chunk counts and ingest times
+    List,
+
+    /// Print a document reassembled from its chunks
+    Show {
+        /// Source path, as shown by 'know docs list'
+        source: String,
+    },
+
+    /// Remove every point ingested from a source
+    Rm {
+        /// Source path, or a glob matching several (e.g. 'docs/old/*')
+        source: String,
+
+        /// Only show what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CollectionsCommand {
+    /// List collections with their size, embedding model and aliases
+    List,
+
+    /// Show the details of a collection (defaults to --collection)
+    Info {
+        /// Collection or alias name
+        name: Option<String>,
+    },
+
+    /// Create an empty collection, choosing how it is indexed and stored
+    Create {
+        /// Collection name
+        name: String,
+
+        /// Dimensions of the vectors [default: those of the embedding model]
+        #[arg(long)]
+        dimensions: Option<usize>,
+
+        #[command(flatten)]
+        storage: StorageSettings,
+    },
+
+    /// Copy a collection and its metadata into a new collection
+    Copy {
+        /// Collection to copy
+        from: String,
+        /// Name of the new collection
+        to: String,
+    },
+
+    /// Rename a collection, moving any aliases that point at it
+    Rename {
+        /// Current name
+        from: String,
+        /// New name
+        to: String,
+    },
+
+    /// Point an alias at a collection, atomically switching it if it exists
+    Alias {
+        /// Alias name, usable anywhere a collection name is
+        alias: String,
+
+        /// Collection the alias should point at
+        #[arg(required_unless_present = "delete")]
+        collection: Option<String>,
+
+        /// Remove the alias instead
+        #[arg(long, conflicts_with = "collection")]
+        delete: bool,
+    },
+}
+
 #[derive(Clone, ValueEnum, Debug, PartialEq)]
 pub enum BackendType {
     /// Docker Model Runner (default)
@@ -124,3 +383,140 @@ pub enum BackendType {
     /// OpenAI-compatible API
     Openai,
 }
+
+/// Retrieval options for querying the knowledge base
+#[derive(Args, Clone, Debug)]
+pub struct RetrievalArgs {
+    /// How to retrieve context (summaries requires ingesting with --summarize)
+    #[arg(long, value_enum, default_value = "chunks")]
+    pub retrieval: RetrievalMode,
+
+    /// How to turn the question into search queries
+    #[arg(long, value_enum, default_value = "raw")]
+    pub query_mode: QueryMode,
+
+    /// Number of paraphrases to search in multi-query mode
+    #[arg(long, default_value = "3")]
+    pub num_queries: usize,
+
+    /// Diversify results with maximal marginal relevance; 1.0 is pure relevance,
+    /// 0.0 pure diversity
+    #[arg(long, value_parser = parse_mmr_lambda)]
+    pub mmr_lambda: Option<f32>,
+
+    /// Maximum number of chunks to use from any one source
+    #[arg(long)]
+    pub max_per_source: Option<usize>,
+
+    /// Add this many neighbouring chunks before and after every hit
+    #[arg(long, default_value = "0")]
+    pub expand: usize,
+
+    /// Ignore hits with a similarity score below this (0.0-1.0 for cosine)
+    #[arg(long)]
+    pub min_score: Option<f32>,
+
+    /// Only search points whose payload field matches, as key=value
+    /// (e.g. source=docs/setup.md, page=3); repeat to require several
+    #[arg(long, value_parser = parse_filter)]
+    pub filter: Vec<Condition>,
+}
+
+/// Parse an MMR lambda, which must be between 0.0 and 1.0
+fn parse_mmr_lambda(s: &str) -> Result<f32, String> {
+    let lambda: f32 = s.parse().map_err(|_| format!("invalid number '{}'", s))?;
+    if !(0.0..=1.0).contains(&lambda) {
+        return Err(format!("{} is not between 0.0 and 1.0", lambda));
+    }
+    Ok(lambda)
+}
+
+/// Parse a `key=value` payload filter; integer values match numeric fields
+fn parse_filter(s: &str) -> Result<Condition, String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid filter '{}', expected key=value", s))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("invalid filter '{}', missing key", s));
+    }
+
+    Ok(match value.parse::<i64>() {
+        Ok(number) => Condition::matches(key, number),
+        Err(_) => Condition::matches(key, value),
+    })
+}
+
+impl RetrievalArgs {
+    pub fn options(&self, top_k: usize) -> RetrievalOptions {
+        RetrievalOptions {
+            top_k,
+            mode: self.retrieval,
+            query_mode: self.query_mode,
+            num_queries: self.num_queries,
+            mmr_lambda: self.mmr_lambda,
+            max_per_source: self.max_per_source,
+            expand: self.expand,
+            min_score: self.min_score,
+            filter: self.filter.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// The answer followed by a list of sources
+    Text,
+    /// A pretty-printed JSON object with the answer, chunks, timings and token usage
+    Json,
+    /// The same object as json, on a single line
+    Jsonl,
+    /// The answer and sources as a markdown document
+    Markdown,
+}
+
+/// Options for checking answers against their sources
+#[derive(Args, Clone, Debug)]
+pub struct VerifyArgs {
+    /// Check every statement of the answer against the retrieved chunks and
+    /// mark unsupported ones (--verify=embedding for the cheaper similarity check)
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "llm"
+    )]
+    pub verify: Option<VerifyMode>,
+
+    /// Minimum similarity for a statement to count as supported with --verify embedding
+    #[arg(long)]
+    pub verify_threshold: Option<f32>,
+}
+
+/// Near-duplicate chunk detection options for ingest
+#[derive(Args, Clone, Debug)]
+pub struct DedupArgs {
+    /// What to do with near-duplicate chunks
+    #[arg(long, value_enum, default_value = "keep")]
+    pub duplicates: DuplicateMode,
+
+    /// Maximum SimHash distance in bits for two chunks to count as near-duplicates
+    #[arg(long, default_value = "8")]
+    pub dedup_distance: u32,
+
+    /// Also treat chunks whose embedding has at least this cosine similarity
+    /// to a point already in the collection as duplicates
+    #[arg(long)]
+    pub dedup_similarity: Option<f32>,
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq)]
+pub enum DuplicateMode {
+    /// Store duplicates like any other chunk
+    Keep,
+    /// Drop duplicates
+    Skip,
+    /// Drop duplicates but record their source on the original chunk
+    Merge,
+}
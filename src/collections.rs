@@ -0,0 +1,261 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+
+use crate::backend::CollectionBackends;
+use crate::cli::{Cli, CollectionsCommand};
+use crate::config::Config;
+use crate::qdrant::{Alias, CollectionMetadata, StorageSettings};
+use crate::store::{self, VectorStore};
+
+/// Run a `know collections` subcommand
+pub async fn collections(cli: &Cli, command: &CollectionsCommand) -> Result<()> {
+    let store = store::create_store(cli).await?;
+    if !store.is_available().await {
+        anyhow::bail!(store::unavailable_message(cli));
+    }
+
+    match command {
+        CollectionsCommand::List => list(&*store).await,
+        CollectionsCommand::Info { name } => {
+            let name = match name {
+                Some(name) => name.as_str(),
+                None => cli.collection()?,
+            };
+            info(&*store, name).await
+        }
+        CollectionsCommand::Create {
+            name,
+            dimensions,
+            storage,
+        } => create(cli, &*store, name, *dimensions, storage).await,
+        CollectionsCommand::Copy { from, to } => {
+            println!("Copying collection '{}' to '{}'...", from, to);
+            let copied = store.copy_collection(from, to).await?;
+            println!("Copied {} points.", copied);
+            Ok(())
+        }
+        CollectionsCommand::Rename { from, to } => rename(&*store, from, to).await,
+        CollectionsCommand::Alias {
+            alias,
+            collection,
+            delete,
+        } => {
+            if *delete {
+                store.delete_alias(alias).await?;
+                println!("Alias '{}' removed.", alias);
+                return Ok(());
+            }
+            let collection = collection
+                .as_deref()
+                .context("A collection is required unless --delete is given")?;
+            set_alias(&*store, alias, collection).await
+        }
+    }
+}
+
+/// Aliases pointing at `collection`, as a comma-separated list
+fn aliases_of(aliases: &[Alias], collection: &str) -> String {
+    aliases
+        .iter()
+        .filter(|a| a.collection == collection)
+        .map(|a| a.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+async fn list(store: &dyn VectorStore) -> Result<()> {
+    let names = store.list_collections().await?;
+    if names.is_empty() {
+        println!("No collections. Run 'know ingest <path>' to create one.");
+        return Ok(());
+    }
+    let aliases = store.aliases().await?;
+
+    println!(
+        "{:<24} {:>8} {:>6}  {:<32} {:<20}  ALIASES",
+        "NAME", "POINTS", "DIMS", "EMBED MODEL", "LAST INGEST"
+    );
+    for name in &names {
+        let info = store.collection_info(name).await?;
+        let metadata = store.get_metadata(name).await?.unwrap_or_default();
+        println!(
+            "{:<24} {:>8} {:>6}  {:<32} {:<20}  {}",
+            name,
+            info.as_ref().map_or(0, |i| i.points_count),
+            info.as_ref()
+                .and_then(|i| i.vector_size)
+                .map_or("-".to_string(), |s| s.to_string()),
+            metadata.embed_model.as_deref().unwrap_or("-"),
+            metadata.last_ingest.as_deref().unwrap_or("-"),
+            aliases_of(&aliases, name),
+        );
+    }
+
+    Ok(())
+}
+
+async fn info(store: &dyn VectorStore, name: &str) -> Result<()> {
+    let aliases = store.aliases().await?;
+
+    // Show the collection behind an alias
+    let collection = match aliases.iter().find(|a| a.name == name) {
+        Some(alias) => {
+            println!("'{}' is an alias of '{}'\n", name, alias.collection);
+            alias.collection.as_str()
+        }
+        None => name,
+    };
+
+    let info = store
+        .collection_info(collection)
+        .await?
+        .with_context(|| format!("Collection '{}' not found", collection))?;
+    let metadata = store.get_metadata(collection).await?.unwrap_or_default();
+    let sources = store.count_sources(collection).await?;
+
+    let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+
+    println!("Collection:   {}", collection);
+    println!("Points:       {}", info.points_count);
+    println!("Sources:      {}", sources);
+    println!(
+        "Vector size:  {}",
+        or_unknown(info.vector_size.map(|s| s.to_string()))
+    );
+    println!("Distance:     {}", or_unknown(info.distance));
+    println!(
+        "HNSW:         m={}, ef_construct={}",
+        or_unknown(info.hnsw_m.map(|m| m.to_string())),
+        or_unknown(info.hnsw_ef_construct.map(|ef| ef.to_string()))
+    );
+    println!(
+        "Storage:      vectors {}, payload {}, {}",
+        if info.on_disk { "on disk" } else { "in RAM" },
+        if info.on_disk_payload {
+            "on disk"
+        } else {
+            "in RAM"
+        },
+        match &info.quantization {
+            Some(quantization) => format!("{} quantization", quantization),
+            None => "not quantized".to_string(),
+        }
+    );
+    if let Some(shards) = info.shards {
+        println!("Shards:       {}", shards);
+    }
+    if !metadata.search.is_empty() {
+        let search = &metadata.search;
+        let mut params = Vec::new();
+        if let Some(ef) = search.hnsw_ef {
+            params.push(format!("ef={}", ef));
+        }
+        if let Some(rescore) = search.rescore {
+            params.push(format!("rescore={}", rescore));
+        }
+        if let Some(oversampling) = search.oversampling {
+            params.push(format!("oversampling={}", oversampling));
+        }
+        println!("Search:       {}", params.join(", "));
+    }
+    println!("Embed model:  {}", or_unknown(metadata.embed_model));
+    println!("Backend:      {}", or_unknown(metadata.backend));
+    println!(
+        "Chunking:     {}",
+        or_unknown(metadata.chunk_size.map(|size| match &metadata.chunker {
+            Some(chunker) => format!("{}, {} characters", chunker, size),
+            None => format!("{} characters", size),
+        }))
+    );
+    println!("Last ingest:  {}", or_unknown(metadata.last_ingest));
+    println!(
+        "Written by:   {}",
+        or_unknown(metadata.version.map(|v| format!("know {}", v)))
+    );
+    println!(
+        "Prompt:       {}",
+        if metadata.prompt.is_some() {
+            "stored with the collection"
+        } else {
+            "default"
+        }
+    );
+
+    let pointing = aliases_of(&aliases, collection);
+    if !pointing.is_empty() {
+        println!("Aliases:      {}", pointing);
+    }
+
+    Ok(())
+}
+
+/// Create `name` with `storage` settings, falling back to those in the config
+async fn create(
+    cli: &Cli,
+    store: &dyn VectorStore,
+    name: &str,
+    dimensions: Option<usize>,
+    storage: &StorageSettings,
+) -> Result<()> {
+    if store.collection_info(name).await?.is_some() {
+        anyhow::bail!("Collection '{}' already exists", name);
+    }
+    let config = Config::load(cli.config.as_deref())?;
+    let settings = storage.clone().or(&config.storage(name));
+
+    let mut metadata = CollectionMetadata {
+        search: settings.search_params(),
+        ..Default::default()
+    };
+    let vector_size = match dimensions {
+        Some(dimensions) => dimensions,
+        None => {
+            let backends = CollectionBackends::new(cli, &config, store, [name]).await?;
+            let backend = backends.for_collection(name)?;
+            metadata.embed_model = Some(backend.embed_model().to_string());
+            metadata.backend = Some(backend.name().to_string());
+            backend.embed("test").await?.len()
+        }
+    };
+    metadata.dimensions = Some(vector_size);
+
+    store
+        .ensure_collection(name, vector_size, &settings)
+        .await?;
+    store.set_metadata(name, &metadata).await?;
+    println!(
+        "Created collection '{}' for {}-dimensional vectors.",
+        name, vector_size
+    );
+
+    Ok(())
+}
+
+async fn rename(store: &dyn VectorStore, from: &str, to: &str) -> Result<()> {
+    println!("Copying collection '{}' to '{}'...", from, to);
+    let copied = store.rename_collection(from, to).await?;
+    println!("Renamed '{}' to '{}' ({} points).", from, to, copied);
+
+    Ok(())
+}
+
+async fn set_alias(store: &dyn VectorStore, alias: &str, collection: &str) -> Result<()> {
+    if store.list_collections().await?.iter().any(|c| c == alias) {
+        anyhow::bail!(
+            "A collection named '{}' exists. Rename it first (know collections rename {} {}-old) \
+            so the name can become an alias.",
+            alias,
+            alias,
+            alias
+        );
+    }
+    if store.collection_info(collection).await?.is_none() {
+        anyhow::bail!("Collection '{}' not found", collection);
+    }
+
+    store.set_alias(alias, collection).await?;
+    println!("Alias '{}' now points at '{}'.", alias, collection);
+
+    Ok(())
+}
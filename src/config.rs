@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::qdrant::StorageSettings;
+
+/// Settings read from the know config file.
+///
+/// ```toml
+/// [prompts]
+/// legal = """
+/// Answer strictly from the context and quote it verbatim...
+/// {{context}}
+/// """
+///
+/// [collections.hr]
+/// prompt = "legal"
+///
+/// [collections.hr.storage]
+/// quantization = "scalar"
+/// on_disk = true
+/// ```
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Named prompt templates
+    pub prompts: HashMap<String, String>,
+    /// Per-collection settings, keyed by collection name
+    pub collections: HashMap<String, CollectionConfig>,
+    /// Directory holding the config file, for resolving relative paths
+    #[serde(skip)]
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollectionConfig {
+    /// Prompt template name or file used when querying the collection
+    pub prompt: Option<String>,
+    /// Embedding model the collection was built with, when it isn't the default
+    pub embed_model: Option<String>,
+    /// How the collection is indexed and stored when created, and searched
+    pub storage: StorageSettings,
+}
+
+impl Config {
+    /// Default location of the config file: `~/.config/know/config.toml` on Linux
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("know").join("config.toml"))
+    }
+
+    /// Load the config from `path`, or from the default location; a missing
+    /// default config file just means no settings
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default())
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read config {}", path.display()))
+            }
+        };
+
+        let mut config: Config = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse config {}", path.display()))?;
+        config.dir = path.parent().map(Path::to_path_buf);
+        Ok(config)
+    }
+
+    /// Settings for a collection, if the config has any
+    pub fn collection(&self, name: &str) -> Option<&CollectionConfig> {
+        self.collections.get(name)
+    }
+
+    /// Storage settings for a collection, empty if the config has none
+    pub fn storage(&self, name: &str) -> StorageSettings {
+        self.collection(name)
+            .map(|c| c.storage.clone())
+            .unwrap_or_default()
+    }
+}
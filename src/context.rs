@@ -0,0 +1,272 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::backend::LlmBackend;
+use crate::qdrant::{PointKind, ScoredChunk};
+
+/// Context window assumed when it is neither configured nor reported by the backend
+const DEFAULT_CONTEXT_LENGTH: usize = 4096;
+
+/// Tokens set aside for the system prompt wording and chat formatting
+const PROMPT_OVERHEAD_TOKENS: usize = 128;
+
+/// Don't bother keeping a trimmed chunk with less room than this
+const MIN_TRIMMED_TOKENS: usize = 64;
+
+const SEPARATOR: &str = "\n---\n";
+
+/// Rough token count that errs on the high side: about four characters per
+/// token for ASCII text, and one token per character for everything else
+pub fn estimate_tokens(text: &str) -> usize {
+    let (ascii, other) = text.chars().fold((0usize, 0usize), |(ascii, other), c| {
+        if c.is_ascii() {
+            (ascii + 1, other)
+        } else {
+            (ascii, other + 1)
+        }
+    });
+    ascii.div_ceil(4) + other
+}
+
+/// Cut text down to roughly `tokens` tokens, preferring a whitespace boundary
+fn truncate_to_tokens(text: &str, tokens: usize) -> String {
+    let mut used = 0;
+    let mut end = 0;
+    for (i, c) in text.char_indices() {
+        used += if c.is_ascii() { 1 } else { 4 };
+        if used > tokens * 4 {
+            break;
+        }
+        end = i + c.len_utf8();
+    }
+
+    let cut = &text[..end];
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(i) if i > end / 2 => &cut[..i],
+        _ => cut,
+    };
+    format!("{} [...]", cut.trim_end())
+}
+
+/// Numbered label introducing a retrieved chunk in the context; the number
+/// is what the model cites
+fn block_header(hit: &ScoredChunk, number: usize) -> String {
+    let chunk = &hit.chunk;
+    let page = chunk
+        .page
+        .map(|page| format!(", page {}", page))
+        .unwrap_or_default();
+    let collection = hit
+        .collection
+        .as_ref()
+        .map(|collection| format!(", collection {}", collection))
+        .unwrap_or_default();
+    match chunk.kind {
+        PointKind::Summary => format!("[{}] Summary of: {}{}\n", number, chunk.source, collection),
+        PointKind::Chunk | PointKind::Question => {
+            format!(
+                "[{}] Source: {}{}{}\n",
+                number, chunk.source, page, collection
+            )
+        }
+    }
+}
+
+/// Format one retrieved chunk as a labelled block of context
+fn format_block(hit: &ScoredChunk, number: usize) -> String {
+    format!("{}{}\n", block_header(hit, number), hit.chunk.content)
+}
+
+/// Prompt context built from retrieved chunks
+pub struct Context {
+    pub text: String,
+    /// The chunks that made it into the context, in retrieval order; block
+    /// `[n]` of the text is `hits[n - 1]`
+    pub hits: Vec<ScoredChunk>,
+    /// Chunks dropped entirely to fit the budget
+    pub dropped: usize,
+    /// Chunks cut short to fit the budget
+    pub trimmed: usize,
+}
+
+/// Builds prompt context that fits the generation model's context window
+pub struct ContextBuilder {
+    context_length: usize,
+    answer_tokens: usize,
+}
+
+impl ContextBuilder {
+    /// Use the configured context length, else ask the backend, else fall back to a default
+    pub async fn new(
+        backend: &dyn LlmBackend,
+        context_length: Option<usize>,
+        answer_tokens: usize,
+    ) -> Self {
+        let context_length = match context_length {
+            Some(length) => length,
+            None => backend
+                .context_length()
+                .await
+                .unwrap_or(DEFAULT_CONTEXT_LENGTH),
+        };
+
+        Self {
+            context_length,
+            answer_tokens,
+        }
+    }
+
+    pub fn context_length(&self) -> usize {
+        self.context_length
+    }
+
+    /// Tokens available for retrieved context once the prompt, question and answer are accounted for
+    pub fn budget(&self, question: &str) -> usize {
+        self.context_length
+            .saturating_sub(self.answer_tokens)
+            .saturating_sub(PROMPT_OVERHEAD_TOKENS)
+            .saturating_sub(estimate_tokens(question))
+    }
+
+    /// Fit retrieved chunks into the budget, dropping or trimming the lowest
+    /// ranked ones first. `hits` are ranked as retrieved: raw scores can't be
+    /// compared across collections, so they don't decide what is kept.
+    pub fn build(&self, question: &str, hits: Vec<ScoredChunk>) -> Context {
+        let mut remaining = self.budget(question);
+
+        let mut kept = Vec::with_capacity(hits.len());
+        let mut dropped = 0;
+        let mut trimmed = 0;
+
+        // Blocks are numbered once selected, so cost them with the widest number
+        let widest = hits.len();
+
+        for hit in &hits {
+            let cost = estimate_tokens(&format_block(hit, widest)) + estimate_tokens(SEPARATOR);
+
+            if cost <= remaining {
+                remaining -= cost;
+                kept.push(hit.clone());
+            } else if remaining >= MIN_TRIMMED_TOKENS {
+                let overhead = estimate_tokens(&block_header(hit, widest))
+                    + estimate_tokens(SEPARATOR)
+                    + estimate_tokens(" [...]\n");
+                let mut hit = hit.clone();
+                hit.chunk.content =
+                    truncate_to_tokens(&hit.chunk.content, remaining.saturating_sub(overhead));
+                remaining = remaining.saturating_sub(
+                    estimate_tokens(&format_block(&hit, widest)) + estimate_tokens(SEPARATOR),
+                );
+                kept.push(hit);
+                trimmed += 1;
+            } else {
+                dropped += 1;
+            }
+        }
+
+        let text = kept
+            .iter()
+            .enumerate()
+            .map(|(i, hit)| format_block(hit, i + 1))
+            .collect::<Vec<_>>()
+            .join(SEPARATOR);
+
+        Context {
+            text,
+            hits: kept,
+            dropped,
+            trimmed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qdrant::DocumentChunk;
+
+    const QUESTION: &str = "What does the handbook say about leave?";
+
+    fn hits(count: usize, words: usize) -> Vec<ScoredChunk> {
+        (1..=count)
+            .map(|n| ScoredChunk {
+                chunk: DocumentChunk::from_payload(
+                    format!("chunk-{}", n),
+                    serde_json::json!({
+                        "content": vec!["word"; words].join(" "),
+                        "source": format!("doc{}.md", n),
+                    }),
+                )
+                .unwrap(),
+                score: 1.0,
+                vector: None,
+                collection: None,
+                fused_score: None,
+            })
+            .collect()
+    }
+
+    /// Tokens the finished prompt needs besides the context itself
+    fn reserved(builder: &ContextBuilder) -> usize {
+        builder.answer_tokens + PROMPT_OVERHEAD_TOKENS + estimate_tokens(QUESTION)
+    }
+
+    #[test]
+    fn everything_fits_a_large_window() {
+        let builder = ContextBuilder {
+            context_length: 8192,
+            answer_tokens: 512,
+        };
+        let context = builder.build(QUESTION, hits(3, 50));
+        assert_eq!(context.hits.len(), 3);
+        assert_eq!((context.dropped, context.trimmed), (0, 0));
+    }
+
+    #[test]
+    fn hits_over_the_budget_are_trimmed_then_dropped() {
+        let builder = ContextBuilder {
+            context_length: 1024,
+            answer_tokens: 256,
+        };
+        let context = builder.build(QUESTION, hits(10, 200));
+
+        assert_eq!(context.trimmed, 1);
+        assert_eq!(context.hits.len() + context.dropped, 10);
+        assert!(context.dropped > 0);
+        // Lower-ranked hits go first
+        assert_eq!(
+            context.hits.last().unwrap().chunk.id,
+            format!("chunk-{}", context.hits.len())
+        );
+        assert!(context
+            .hits
+            .last()
+            .unwrap()
+            .chunk
+            .content
+            .ends_with(" [...]"));
+
+        assert!(estimate_tokens(&context.text) <= builder.budget(QUESTION));
+        assert!(estimate_tokens(&context.text) + reserved(&builder) <= builder.context_length);
+    }
+
+    #[test]
+    fn the_answer_reservation_is_kept_free() {
+        let builder = ContextBuilder {
+            context_length: 1024,
+            answer_tokens: 900,
+        };
+        let context = builder.build(QUESTION, hits(3, 200));
+
+        assert!(context.hits.is_empty());
+        assert_eq!(context.dropped, 3);
+        assert!(context.text.is_empty());
+
+        // Reserving more than the whole window leaves no room rather than underflowing
+        let builder = ContextBuilder {
+            context_length: 512,
+            answer_tokens: 1024,
+        };
+        assert_eq!(builder.budget(QUESTION), 0);
+        assert!(builder.build(QUESTION, hits(1, 1)).hits.is_empty());
+    }
+}
@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashMap;
+
+/// Number of words per shingle when fingerprinting chunk text
+const SHINGLE_WORDS: usize = 3;
+
+/// Stable 64-bit FNV-1a hash, so fingerprints don't change between builds
+fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Compute a 64-bit SimHash over word shingles of normalised text
+pub fn simhash(text: &str) -> u64 {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+
+    let shingles: Vec<String> = if words.len() < SHINGLE_WORDS {
+        vec![words.join(" ")]
+    } else {
+        words.windows(SHINGLE_WORDS).map(|w| w.join(" ")).collect()
+    };
+
+    let mut weights = [0i64; 64];
+    for shingle in &shingles {
+        let hash = fnv1a(shingle);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, &w)| w > 0)
+        .fold(0u64, |acc, (bit, _)| acc | (1 << bit))
+}
+
+/// Index of SimHash fingerprints supporting near-duplicate lookups.
+///
+/// Fingerprints are split into `max_distance + 1` bands; by the pigeonhole
+/// principle two hashes within `max_distance` bits share at least one band
+/// exactly, so only those candidates need a full Hamming comparison.
+pub struct SimHashIndex<T> {
+    max_distance: u32,
+    bands: HashMap<(usize, u64), Vec<usize>>,
+    entries: Vec<(u64, T)>,
+}
+
+impl<T> SimHashIndex<T> {
+    pub fn new(max_distance: u32) -> Self {
+        Self {
+            max_distance: max_distance.min(63),
+            bands: HashMap::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    fn band_keys(&self, hash: u64) -> impl Iterator<Item = (usize, u64)> {
+        let count = self.max_distance as usize + 1;
+        (0..count).map(move |band| {
+            let start = band * 64 / count;
+            let end = (band + 1) * 64 / count;
+            let width = end - start;
+            let mask = if width == 64 {
+                u64::MAX
+            } else {
+                (1u64 << width) - 1
+            };
+            (band, (hash >> start) & mask)
+        })
+    }
+
+    /// Find an indexed entry within `max_distance` bits of `hash`
+    pub fn find(&self, hash: u64) -> Option<&T> {
+        self.band_keys(hash)
+            .filter_map(|key| self.bands.get(&key))
+            .flatten()
+            .map(|&i| &self.entries[i])
+            .find(|(other, _)| (hash ^ other).count_ones() <= self.max_distance)
+            .map(|(_, value)| value)
+    }
+
+    pub fn insert(&mut self, hash: u64, value: T) {
+        let index = self.entries.len();
+        let keys: Vec<_> = self.band_keys(hash).collect();
+        for key in keys {
+            self.bands.entry(key).or_default().push(index);
+        }
+        self.entries.push((hash, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FOOTER: &str = "This message and any attachments are confidential and intended solely \
+        for the addressee. If you have received it in error, please notify the sender \
+        immediately and delete it from your system. Any unauthorised use, disclosure or \
+        copying is strictly prohibited. The company accepts no liability for any damage \
+        caused by viruses transmitted by this email or its attachments. Registered in \
+        England and Wales under company number 01234567, with its registered office at \
+        1 High Street, London. Emails may be monitored and recorded for training, \
+        compliance and security purposes, and replies may be read by other members of staff.";
+
+    fn distance(a: &str, b: &str) -> u32 {
+        (simhash(a) ^ simhash(b)).count_ones()
+    }
+
+    #[test]
+    fn identical_text_has_the_same_fingerprint() {
+        assert_eq!(simhash(FOOTER), simhash(FOOTER));
+        // Case, punctuation and spacing are normalised away
+        let reformatted = FOOTER
+            .to_uppercase()
+            .replace(", ", " ; ")
+            .replace(". ", "\n\n");
+        assert_eq!(simhash(FOOTER), simhash(&reformatted));
+    }
+
+    #[test]
+    fn near_identical_text_is_within_the_default_distance() {
+        // The same footer from a sister company
+        let edited = FOOTER.replace("01234567", "07654321");
+        assert_ne!(simhash(FOOTER), simhash(&edited));
+        assert!(distance(FOOTER, &edited) <= 8);
+
+        let mut index = SimHashIndex::new(8);
+        index.insert(simhash(FOOTER), "footer");
+        assert_eq!(index.find(simhash(&edited)), Some(&"footer"));
+    }
+
+    #[test]
+    fn distinct_text_is_beyond_the_default_distance() {
+        let other = "Refunds are issued to the original payment method within fourteen \
+            days of the returned item arriving at our warehouse, provided it is unused \
+            and in its original packaging with the receipt.";
+        assert!(distance(FOOTER, other) > 8);
+
+        let mut index = SimHashIndex::new(8);
+        index.insert(simhash(FOOTER), "footer");
+        assert_eq!(index.find(simhash(other)), None);
+    }
+
+    #[test]
+    fn index_finds_hashes_up_to_the_threshold() {
+        let mut index = SimHashIndex::new(3);
+        index.insert(0, "zero");
+
+        // Differences spread over bands, so no band alone decides the match
+        assert_eq!(index.find(0b111), Some(&"zero"));
+        assert_eq!(index.find(1 | 1 << 20 | 1 << 40), Some(&"zero"));
+        assert_eq!(index.find(1 | 1 << 20 | 1 << 40 | 1 << 60), None);
+        assert_eq!(index.find(0b1111), None);
+    }
+}
@@ -0,0 +1,289 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::cli::{Cli, DocsCommand};
+use crate::qdrant::{Condition, Filter, PointKind};
+use crate::store::{self, VectorStore};
+
+/// Per-source totals for `know docs list`
+#[derive(Default)]
+struct SourceStats {
+    chunks: usize,
+    /// Summary and question points
+    generated: usize,
+    /// Chunks stored under another source that this source also contains
+    shared: usize,
+    /// Latest ingest time of the source's points
+    ingested_at: Option<String>,
+}
+
+/// Run a `know docs` subcommand
+pub async fn docs(cli: &Cli, command: &DocsCommand) -> Result<()> {
+    let collection = cli.collection()?;
+    let store = store::create_store(cli).await?;
+    if !store.is_available().await {
+        anyhow::bail!(store::unavailable_message(cli));
+    }
+    if store.collection_info(collection).await?.is_none() {
+        anyhow::bail!("Collection '{}' not found", collection);
+    }
+
+    match command {
+        DocsCommand::List => list(&*store, collection).await,
+        DocsCommand::Show { source } => show(&*store, collection, source).await,
+        DocsCommand::Rm { source, dry_run } => remove(&*store, collection, source, *dry_run).await,
+    }
+}
+
+/// Chunk counts and ingest times of every source in the collection
+async fn source_stats(
+    store: &dyn VectorStore,
+    collection: &str,
+) -> Result<BTreeMap<String, SourceStats>> {
+    let payloads = store
+        .scroll_fields(
+            collection,
+            &Filter::default(),
+            &["source", "kind", "ingested_at", "other_sources"],
+        )
+        .await?;
+
+    let mut sources: BTreeMap<String, SourceStats> = BTreeMap::new();
+    for payload in payloads {
+        let Some(source) = payload.get("source").and_then(|s| s.as_str()) else {
+            continue;
+        };
+
+        // Points written before kinds existed are chunks
+        let kind: PointKind = payload
+            .get("kind")
+            .and_then(|k| serde_json::from_value(k.clone()).ok())
+            .unwrap_or_default();
+        if kind == PointKind::Chunk {
+            let others = payload.get("other_sources").and_then(|o| o.as_array());
+            for other in others.into_iter().flatten().filter_map(|o| o.as_str()) {
+                sources.entry(other.to_string()).or_default().shared += 1;
+            }
+        }
+
+        let stats = sources.entry(source.to_string()).or_default();
+        match kind {
+            PointKind::Chunk => stats.chunks += 1,
+            PointKind::Summary | PointKind::Question => stats.generated += 1,
+        }
+
+        if let Some(at) = payload.get("ingested_at").and_then(|a| a.as_str()) {
+            if stats
+                .ingested_at
+                .as_deref()
+                .is_none_or(|latest| at > latest)
+            {
+                stats.ingested_at = Some(at.to_string());
+            }
+        }
+    }
+
+    Ok(sources)
+}
+
+async fn list(store: &dyn VectorStore, collection: &str) -> Result<()> {
+    let sources = source_stats(store, collection).await?;
+    if sources.is_empty() {
+        println!("No documents in collection '{}'.", collection);
+        return Ok(());
+    }
+
+    let width = sources
+        .keys()
+        .map(|s| s.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+    println!(
+        "{:<width$}  {:>6}  {:>6}  {:>9}  INGESTED",
+        "SOURCE", "CHUNKS", "SHARED", "GENERATED"
+    );
+    for (source, stats) in &sources {
+        println!(
+            "{:<width$}  {:>6}  {:>6}  {:>9}  {}",
+            source,
+            stats.chunks,
+            stats.shared,
+            stats.generated,
+            stats.ingested_at.as_deref().unwrap_or("-"),
+        );
+    }
+
+    let chunks: usize = sources.values().map(|s| s.chunks).sum();
+    println!(
+        "\n{} documents, {} chunks in collection '{}'",
+        sources.len(),
+        chunks,
+        collection
+    );
+
+    Ok(())
+}
+
+async fn show(store: &dyn VectorStore, collection: &str, source: &str) -> Result<()> {
+    let filter = Filter::chunks().with(Condition::matches("source", source));
+    let mut chunks = store.scroll(collection, &filter).await?;
+    if chunks.is_empty() {
+        anyhow::bail!(
+            "No document '{}' in collection '{}'. Run 'know docs list' to see the sources.",
+            source,
+            collection
+        );
+    }
+
+    // Oldest ingest first, then document order
+    chunks.sort_by(|a, b| {
+        (&a.ingested_at, &a.doc_id, a.position).cmp(&(&b.ingested_at, &b.doc_id, b.position))
+    });
+
+    let ingests = {
+        let mut doc_ids: Vec<&Option<String>> = chunks.iter().map(|c| &c.doc_id).collect();
+        doc_ids.dedup();
+        doc_ids.len()
+    };
+    if ingests > 1 {
+        eprintln!(
+            "Warning: '{}' was ingested {} times; each copy is shown in turn.",
+            source, ingests
+        );
+    }
+
+    let mut doc_id = None;
+    for chunk in &chunks {
+        if ingests > 1 && doc_id != Some(&chunk.doc_id) {
+            println!(
+                "===== ingested {} =====\n",
+                chunk.ingested_at.as_deref().unwrap_or("at an unknown time")
+            );
+            doc_id = Some(&chunk.doc_id);
+        }
+        println!("{}\n", chunk.content);
+    }
+
+    Ok(())
+}
+
+/// Whether a pattern should be matched as a glob rather than a literal source
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+async fn remove(
+    store: &dyn VectorStore,
+    collection: &str,
+    pattern: &str,
+    dry_run: bool,
+) -> Result<()> {
+    let sources = source_stats(store, collection).await?;
+
+    let matched: Vec<(&String, &SourceStats)> = if is_glob(pattern) {
+        let glob = glob::Pattern::new(pattern)
+            .with_context(|| format!("Invalid source pattern '{}'", pattern))?;
+        sources
+            .iter()
+            .filter(|(source, _)| glob.matches(source))
+            .collect()
+    } else {
+        sources
+            .iter()
+            .filter(|(source, _)| *source == pattern)
+            .collect()
+    };
+
+    if matched.is_empty() {
+        anyhow::bail!(
+            "No document matching '{}' in collection '{}'. Run 'know docs list' to see the sources.",
+            pattern,
+            collection
+        );
+    }
+
+    for (source, stats) in matched {
+        let points = stats.chunks + stats.generated;
+        if dry_run {
+            println!("Would remove {} ({} points)", source, points);
+            continue;
+        }
+        let kept = remove_source(store, collection, source).await?;
+        if kept > 0 {
+            println!(
+                "Removed {} ({} points, {} kept under the other sources sharing them)",
+                source,
+                points - kept,
+                kept
+            );
+        } else {
+            println!("Removed {} ({} points)", source, points);
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove a source from a collection, returning the number of its points that
+/// were kept because other sources share them.
+///
+/// A chunk that other sources share is handed to the first of them along with
+/// its generated questions, and the source is struck from the other sources of
+/// chunks stored under a different one.
+async fn remove_source(store: &dyn VectorStore, collection: &str, source: &str) -> Result<usize> {
+    let owned = store
+        .scroll(
+            collection,
+            &Filter::default().with(Condition::matches("source", source)),
+        )
+        .await?;
+
+    let mut promoted: HashMap<String, (String, Vec<String>)> = HashMap::new();
+    for chunk in owned.iter().filter(|c| c.kind == PointKind::Chunk) {
+        if let Some((next, rest)) = chunk.other_sources.split_first() {
+            promoted.insert(chunk.id.clone(), (next.clone(), rest.to_vec()));
+        }
+    }
+    let mut kept = 0;
+    for point in &owned {
+        let owner = match point.kind {
+            PointKind::Chunk => Some(&point.id),
+            PointKind::Question => point.parent_id.as_ref(),
+            PointKind::Summary => None,
+        };
+        if let Some((next, rest)) = owner.and_then(|id| promoted.get(id)) {
+            store.set_sources(collection, &point.id, next, rest).await?;
+            kept += 1;
+        }
+    }
+
+    // What wasn't handed on still carries the source
+    store
+        .delete_points(
+            collection,
+            &Filter::default().with(Condition::matches("source", source)),
+        )
+        .await?;
+
+    let shared = store
+        .scroll(
+            collection,
+            &Filter::default().with(Condition::matches("other_sources", source)),
+        )
+        .await?;
+    for point in shared {
+        let others: Vec<String> = point
+            .other_sources
+            .into_iter()
+            .filter(|other| other != source)
+            .collect();
+        store
+            .set_sources(collection, &point.id, &point.source, &others)
+            .await?;
+    }
+
+    Ok(kept)
+}
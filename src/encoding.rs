@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// How many leading bytes to inspect when sniffing for binary content
+const SNIFF_LEN: usize = 8192;
+
+/// Guess UTF-16 without a BOM from the position of NUL bytes in ASCII-heavy text
+fn sniff_utf16(sample: &[u8]) -> Option<&'static Encoding> {
+    if sample.len() < 4 {
+        return None;
+    }
+
+    let pairs = sample.len() / 2;
+    let even_nuls = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_nuls = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|&&b| b == 0)
+        .count();
+
+    // Mostly-ASCII UTF-16 has a NUL in nearly every high byte and almost none elsewhere
+    if odd_nuls * 10 >= pairs * 4 && even_nuls * 20 <= pairs {
+        Some(UTF_16LE)
+    } else if even_nuls * 10 >= pairs * 4 && odd_nuls * 20 <= pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Check whether a sample looks like binary data rather than text
+fn looks_binary(sample: &[u8]) -> bool {
+    if sample.contains(&0) {
+        return true;
+    }
+
+    // Allow common whitespace and escape sequences; anything else below 0x20 is suspicious
+    let control = sample
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+
+    control * 10 > sample.len()
+}
+
+/// Decode raw file bytes as text.
+///
+/// A byte order mark wins if present; otherwise UTF-16 is sniffed from NUL
+/// patterns, valid UTF-8 is taken as-is, and anything else is handed to
+/// statistical detection (Windows-1252, Shift-JIS, etc). Binary data is
+/// rejected with an error instead of being decoded into garbage.
+pub fn decode(data: &[u8]) -> Result<String> {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(data) {
+        let (text, _) = encoding.decode_without_bom_handling(&data[bom_len..]);
+        return Ok(text.into_owned());
+    }
+
+    let sample = &data[..data.len().min(SNIFF_LEN)];
+
+    if let Some(encoding) = sniff_utf16(sample) {
+        let (text, _) = encoding.decode_without_bom_handling(data);
+        return Ok(text.into_owned());
+    }
+
+    if looks_binary(sample) {
+        anyhow::bail!("binary file (not text)");
+    }
+
+    if let Ok(text) = std::str::from_utf8(data) {
+        return Ok(text.to_string());
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(data, true);
+    let encoding = detector.guess(None, true);
+
+    let (text, had_errors) = encoding.decode_without_bom_handling(data);
+    if had_errors && encoding == UTF_8 {
+        anyhow::bail!("unrecognised text encoding");
+    }
+
+    Ok(text.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| {
+                if big_endian {
+                    unit.to_be_bytes()
+                } else {
+                    unit.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn utf8_bom_is_dropped() {
+        assert_eq!(decode(b"\xEF\xBB\xBFcaf\xC3\xA9").unwrap(), "café");
+    }
+
+    #[test]
+    fn utf16_with_bom_is_decoded() {
+        let text = "Grüße aus Köln";
+
+        let mut little = vec![0xFF, 0xFE];
+        little.extend(utf16(text, false));
+        assert_eq!(decode(&little).unwrap(), text);
+
+        let mut big = vec![0xFE, 0xFF];
+        big.extend(utf16(text, true));
+        assert_eq!(decode(&big).unwrap(), text);
+    }
+
+    #[test]
+    fn utf16_without_bom_is_sniffed() {
+        let text = "Plain ASCII notes saved by an old Windows editor";
+        assert_eq!(decode(&utf16(text, false)).unwrap(), text);
+        assert_eq!(decode(&utf16(text, true)).unwrap(), text);
+    }
+
+    #[test]
+    fn latin1_falls_back_to_detection() {
+        let text = "Le café était déjà fermé, mais la crème brûlée à la française reste \
+            très appréciée des élèves qui préfèrent les desserts légers.";
+        let latin1: Vec<u8> = text.chars().map(|c| c as u8).collect();
+        assert!(std::str::from_utf8(&latin1).is_err());
+        assert_eq!(decode(&latin1).unwrap(), text);
+    }
+
+    #[test]
+    fn binary_input_is_rejected() {
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\x00\x00\x01\x00\x00\x00\x01\x00\x08\x06";
+        assert!(decode(png).is_err());
+
+        let controls: Vec<u8> = (0..64)
+            .map(|i| if i % 4 == 0 { b'a' } else { 0x01 })
+            .collect();
+        assert!(decode(&controls).is_err());
+    }
+}
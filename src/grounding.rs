@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::LlmBackend;
+use crate::context::Context;
+use crate::retrieval::cosine_similarity;
+
+/// Similarity a statement needs with some chunk to count as supported in embedding mode
+const DEFAULT_THRESHOLD: f32 = 0.7;
+
+/// Statements with fewer words than this (headings, list labels) aren't checked
+const MIN_STATEMENT_WORDS: usize = 3;
+
+/// Marker placed after statements the sources don't support
+const UNSUPPORTED_MARKER: &str = " [unsupported]";
+
+/// System prompt for the llm verification mode
+const VERIFY_PROMPT: &str = "Check each numbered statement against the context you are given. \
+    A statement is supported only if the context states or directly implies it. Reply with \
+    one line per statement in the form `<number>: yes` or `<number>: no`, and nothing else.";
+
+/// How answer statements are checked against the retrieved chunks
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyMode {
+    /// Ask the generation model whether the context supports each statement
+    #[default]
+    Llm,
+    /// Compare each statement's embedding with the retrieved chunks
+    Embedding,
+}
+
+/// One statement of an answer and whether the sources support it
+#[derive(Serialize, Debug, Clone)]
+pub struct Statement {
+    pub text: String,
+    pub supported: bool,
+    /// Best similarity with a retrieved chunk (embedding mode)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+    /// Number of the context block most similar to the statement (embedding mode)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<usize>,
+    /// Byte offset just past the statement in the answer
+    #[serde(skip)]
+    end: usize,
+}
+
+/// Result of checking an answer against the chunks it was generated from
+#[derive(Serialize, Debug, Clone)]
+pub struct Grounding {
+    pub mode: VerifyMode,
+    pub statements: Vec<Statement>,
+    /// Number of statements the sources don't support
+    pub unsupported: usize,
+}
+
+impl Grounding {
+    /// The answer with every unsupported statement visibly marked
+    pub fn mark(&self, answer: &str) -> String {
+        let mut marked = String::with_capacity(answer.len());
+        let mut last = 0;
+        for statement in self.statements.iter().filter(|s| !s.supported) {
+            marked.push_str(&answer[last..statement.end]);
+            marked.push_str(UNSUPPORTED_MARKER);
+            last = statement.end;
+        }
+        marked.push_str(&answer[last..]);
+        marked
+    }
+}
+
+/// Split text into sentences, returning each sentence's byte range.
+///
+/// A sentence ends at a newline, or at `.`, `!` or `?` followed by whitespace,
+/// with any trailing citations such as `[1]` and closing quotes kept with it.
+fn split_sentences(text: &str) -> Vec<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let end = match bytes[i] {
+            b'\n' => Some(i),
+            b'.' | b'!' | b'?' => {
+                let mut end = i + 1;
+                loop {
+                    match bytes.get(end) {
+                        Some(b'"' | b'\'' | b')') => end += 1,
+                        Some(b'[') => match text[end..].find(']') {
+                            Some(close) => end += close + 1,
+                            None => break,
+                        },
+                        _ => break,
+                    }
+                }
+                match bytes.get(end) {
+                    None => Some(end),
+                    Some(c) if c.is_ascii_whitespace() => Some(end),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        match end {
+            Some(end) => {
+                sentences.push((start, end));
+                start = end;
+                i = end.max(i + 1);
+            }
+            None => i += 1,
+        }
+    }
+    sentences.push((start, text.len()));
+
+    // Trim surrounding whitespace and list markers, and drop fragments
+    sentences
+        .into_iter()
+        .filter_map(|(start, end)| {
+            let raw = &text[start..end];
+            let trimmed = raw.trim_start_matches(|c: char| {
+                c.is_whitespace() || matches!(c, '-' | '*' | '#' | '>')
+            });
+            let start = start + (raw.len() - trimmed.len());
+            let end = start + trimmed.trim_end().len();
+            let words = text[start..end].split_whitespace().count();
+            (words >= MIN_STATEMENT_WORDS).then_some((start, end))
+        })
+        .collect()
+}
+
+/// Remove bracketed citations so they don't affect the check
+fn strip_citations(sentence: &str) -> String {
+    let mut out = String::with_capacity(sentence.len());
+    let mut rest = sentence;
+    while let Some(open) = rest.find('[') {
+        match rest[open..].find(']') {
+            Some(close)
+                if rest[open + 1..open + close]
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == ',' || c == ' ') =>
+            {
+                out.push_str(rest[..open].trim_end());
+                rest = &rest[open + close + 1..];
+            }
+            _ => {
+                out.push_str(&rest[..open + 1]);
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Ask the model for a yes/no verdict on every statement in a single call
+async fn verify_llm(
+    backend: &dyn LlmBackend,
+    claims: &[String],
+    context: &Context,
+) -> Result<Vec<bool>> {
+    let numbered: Vec<String> = claims
+        .iter()
+        .enumerate()
+        .map(|(i, claim)| format!("{}. {}", i + 1, claim))
+        .collect();
+    let prompt = format!(
+        "Context:\n{}\n\nStatements:\n{}",
+        context.text,
+        numbered.join("\n")
+    );
+    let response = backend.chat(VERIFY_PROMPT, &prompt).await?.text;
+
+    // Statements without a clear verdict count as unsupported
+    let mut verdicts = vec![false; claims.len()];
+    for line in response.lines() {
+        let line = line.trim().trim_start_matches(['-', '*', ' ']);
+        let Some((number, verdict)) = line.split_once([':', '.', ')']) else {
+            continue;
+        };
+        let Ok(number) = number.trim().parse::<usize>() else {
+            continue;
+        };
+        let verdict = verdict.trim().to_lowercase();
+        if (1..=claims.len()).contains(&number) {
+            verdicts[number - 1] = verdict.starts_with("yes") || verdict.starts_with("supported");
+        }
+    }
+    Ok(verdicts)
+}
+
+/// Check every statement of `answer` against the chunks in `context`
+pub async fn verify(
+    backend: &dyn LlmBackend,
+    answer: &str,
+    context: &Context,
+    mode: VerifyMode,
+    threshold: Option<f32>,
+) -> Result<Grounding> {
+    let ranges = split_sentences(answer);
+    let claims: Vec<String> = ranges
+        .iter()
+        .map(|&(start, end)| strip_citations(&answer[start..end]))
+        .collect();
+
+    let mut statements: Vec<Statement> = ranges
+        .iter()
+        .map(|&(start, end)| Statement {
+            text: answer[start..end].to_string(),
+            supported: false,
+            score: None,
+            block: None,
+            end,
+        })
+        .collect();
+
+    if !claims.is_empty() {
+        match mode {
+            VerifyMode::Llm => {
+                let verdicts = verify_llm(backend, &claims, context).await?;
+                for (statement, supported) in statements.iter_mut().zip(verdicts) {
+                    statement.supported = supported;
+                }
+            }
+            VerifyMode::Embedding => {
+                let threshold = threshold.unwrap_or(DEFAULT_THRESHOLD);
+                let mut chunks = Vec::with_capacity(context.hits.len());
+                for hit in &context.hits {
+                    chunks.push(backend.embed(&hit.chunk.content).await?);
+                }
+
+                for (statement, claim) in statements.iter_mut().zip(&claims) {
+                    let embedding = backend.embed(claim).await?;
+                    let best = chunks
+                        .iter()
+                        .map(|chunk| cosine_similarity(&embedding, chunk))
+                        .enumerate()
+                        .max_by(|a, b| a.1.total_cmp(&b.1));
+                    if let Some((index, score)) = best {
+                        statement.score = Some(score);
+                        statement.block = Some(index + 1);
+                        statement.supported = score >= threshold;
+                    }
+                }
+            }
+        }
+    }
+
+    let unsupported = statements.iter().filter(|s| !s.supported).count();
+    Ok(Grounding {
+        mode,
+        statements,
+        unsupported,
+    })
+}
@@ -3,106 +3,185 @@
 use anyhow::{Context, Result};
 use glob::glob;
 use indicatif::{ProgressBar, ProgressStyle};
-use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::path::Path;
+use std::time::{Duration, Instant};
 use text_splitter::TextSplitter;
 
-use crate::backend::create_backend;
-use crate::cli::Cli;
-use crate::qdrant::{DocumentChunk, QdrantClient};
+use crate::backend::{parse_list, CollectionBackends, LlmBackend, TokenUsage};
+use crate::citations::{markdown_footnotes, resolve, Citation};
+use crate::cli::{Cli, DedupArgs, DuplicateMode, OutputFormat, RetrievalArgs, VerifyArgs};
+use crate::config::Config;
+use crate::context::ContextBuilder;
+use crate::dedup::{simhash, SimHashIndex};
+use crate::grounding::{self, Grounding};
+use crate::parser::ParserRegistry;
+use crate::prompt::{PromptTemplate, PromptVars};
+use crate::qdrant::{DocumentChunk, Filter, PointKind, ScoredChunk};
+use crate::retrieval::{retrieve, QueryMode, Target};
+use crate::search::location;
+use crate::store;
+use crate::time;
 
 const CHUNK_SIZE: usize = 512; // characters
+const CHUNKER: &str = "text-splitter"; // recorded in collection metadata
+const SUMMARY_INPUT_CHARS: usize = 12_000; // characters of a document sent for summarisation
 
-/// Parse a document using docling service
-async fn parse_with_docling(docling_url: &str, file_path: &Path) -> Result<String> {
-    let client = reqwest::Client::new();
+const SUMMARY_PROMPT: &str = "Summarise the document you are given in three to five \
+    sentences. Name its main topics and what kind of document it is, so the summary can \
+    be used to decide whether the document answers a question. Output only the summary.";
 
-    // Read file content
-    let file_content = tokio::fs::read(file_path).await?;
-    let file_name = file_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("document");
+/// Split text into chunks, with the byte offset each chunk starts at
+fn chunk_text(text: &str) -> Vec<(usize, String)> {
+    let splitter = TextSplitter::new(CHUNK_SIZE);
+
+    // Use character-based chunking as a simple approach
+    // that respects semantic boundaries
+    splitter
+        .chunk_indices(text)
+        .map(|(offset, s)| (offset, s.to_string()))
+        .filter(|(_, s)| !s.trim().is_empty())
+        .collect()
+}
 
-    // Create multipart form
-    let part = reqwest::multipart::Part::bytes(file_content)
-        .file_name(file_name.to_string())
-        .mime_str("application/octet-stream")?;
+/// Markdown headings and page breaks of a document, for locating chunks within it
+struct DocumentOutline {
+    /// Byte offset and text of each ATX heading
+    headings: Vec<(usize, String)>,
+    /// Byte offsets of form feeds separating pages
+    page_breaks: Vec<usize>,
+}
 
-    let form = reqwest::multipart::Form::new().part("files", part);
+impl DocumentOutline {
+    fn new(text: &str) -> Self {
+        let mut headings = Vec::new();
+        let mut offset = 0;
+        for line in text.split_inclusive('\n') {
+            let trimmed = line.trim();
+            let level = trimmed.chars().take_while(|&c| c == '#').count();
+            if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+                headings.push((offset, trimmed[level..].trim().to_string()));
+            }
+            offset += line.len();
+        }
 
-    #[derive(Deserialize)]
-    struct DoclingResponse {
-        document: DoclingDocument,
-    }
+        let page_breaks = text.match_indices('\x0c').map(|(i, _)| i).collect();
 
-    #[derive(Deserialize)]
-    struct DoclingDocument {
-        md_content: String,
+        Self {
+            headings,
+            page_breaks,
+        }
     }
 
-    let response = client
-        .post(format!("{}/v1/convert/file", docling_url))
-        .multipart(form)
-        .send()
-        .await
-        .context("Failed to connect to docling")?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let text = response.text().await.unwrap_or_default();
-        anyhow::bail!("Docling returned error {}: {}", status, text);
+    /// Heading in effect at `offset`, including one starting right there
+    fn heading(&self, offset: usize) -> Option<String> {
+        let index = self.headings.partition_point(|(start, _)| *start <= offset);
+        index.checked_sub(1).map(|i| self.headings[i].1.clone())
     }
 
-    let result: DoclingResponse = response.json().await.context("Failed to parse docling response")?;
-
-    Ok(result.document.md_content)
+    /// One-based page containing `offset`, if the document has page breaks
+    fn page(&self, offset: usize) -> Option<usize> {
+        if self.page_breaks.is_empty() {
+            return None;
+        }
+        Some(self.page_breaks.partition_point(|&i| i < offset) + 1)
+    }
 }
 
-/// Check if docling service is available
-async fn is_docling_available(docling_url: &str) -> bool {
-    reqwest::get(format!("{}/health", docling_url))
-        .await
-        .map(|r| r.status().is_success())
-        .unwrap_or(false)
-}
+/// Generate and embed questions a chunk answers, as points carrying the chunk's payload
+async fn generate_questions(
+    backend: &dyn LlmBackend,
+    chunk: &DocumentChunk,
+    count: usize,
+) -> Result<Vec<(DocumentChunk, Vec<f32>)>> {
+    let system = format!(
+        "Write {} short, distinct questions that are answered by the text you are given, \
+        phrased the way a user would ask them. Output one question per line and nothing else.",
+        count
+    );
+    let response = backend.chat(&system, &chunk.content).await?.text;
+
+    let mut points = Vec::new();
+    for question in parse_list(&response, count) {
+        let embedding = backend.embed(&question).await?;
+        let point = DocumentChunk {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind: PointKind::Question,
+            parent_id: Some(chunk.id.clone()),
+            question: Some(question),
+            ..chunk.clone()
+        };
+        points.push((point, embedding));
+    }
 
-/// Read file content directly (fallback when docling is not available)
-async fn read_file_directly(file_path: &Path) -> Result<String> {
-    let content = tokio::fs::read_to_string(file_path)
-        .await
-        .context("Failed to read file")?;
-    Ok(content)
+    Ok(points)
 }
 
-/// Split text into chunks
-fn chunk_text(text: &str) -> Vec<String> {
-    let splitter = TextSplitter::new(CHUNK_SIZE);
+/// Generate and embed a summary point for a whole document
+async fn summarize_document(
+    backend: &dyn LlmBackend,
+    source: &str,
+    doc_id: &str,
+    content: &str,
+) -> Result<(DocumentChunk, Vec<f32>)> {
+    let excerpt: String = content.chars().take(SUMMARY_INPUT_CHARS).collect();
+    let summary = backend.chat(SUMMARY_PROMPT, &excerpt).await?.text;
+    let embedding = backend.embed(&summary).await?;
+
+    let chunk = DocumentChunk {
+        id: uuid::Uuid::new_v4().to_string(),
+        content: summary.trim().to_string(),
+        source: source.to_string(),
+        kind: PointKind::Summary,
+        parent_id: None,
+        question: None,
+        doc_id: Some(doc_id.to_string()),
+        position: None,
+        heading: None,
+        page: None,
+        other_sources: Vec::new(),
+        ingested_at: None,
+    };
+
+    Ok((chunk, embedding))
+}
 
-    // Use character-based chunking as a simple approach
-    // that respects semantic boundaries
-    splitter
-        .chunks(text)
-        .map(|s| s.to_string())
-        .filter(|s| !s.trim().is_empty())
-        .collect()
+/// Record `source` as another location of the `original` chunk
+fn record_duplicate(
+    merged: &mut HashMap<String, (String, Vec<String>)>,
+    original: &DocumentChunk,
+    source: &str,
+) {
+    let (original_source, others) = merged
+        .entry(original.id.clone())
+        .or_insert_with(|| (original.source.clone(), original.other_sources.clone()));
+
+    if original_source != source && !others.iter().any(|s| s == source) {
+        others.push(source.to_string());
+    }
 }
 
 /// Ingest documents from a path
-pub async fn ingest(cli: &Cli, path: &str, extensions: &str) -> Result<()> {
-    let qdrant = QdrantClient::new(Some(&cli.qdrant_url));
-
-    // Create backend for embeddings
-    let backend = create_backend(cli).await?;
-
-    // Check docling availability
-    let use_docling = is_docling_available(&cli.docling_url).await;
-    if !use_docling {
-        eprintln!(
-            "Warning: Docling not available at {}. Using direct file reading (limited format support).",
-            cli.docling_url
-        );
-    }
+pub async fn ingest(
+    cli: &Cli,
+    path: &str,
+    extensions: &str,
+    dedup: &DedupArgs,
+    summarize: bool,
+    questions: usize,
+) -> Result<()> {
+    let collection = cli.collection()?;
+    let store = store::create_store(cli).await?;
+
+    // Embed with the model the collection was built with, refusing a different one
+    let config = Config::load(cli.config.as_deref())?;
+    let backends = CollectionBackends::new(cli, &config, &*store, [collection]).await?;
+    let backend = backends.for_collection(collection)?;
+    let search = backends.search_params(collection);
+
+    // Set up document parsers (docling if available, built-in otherwise)
+    let parsers = ParserRegistry::new(&cli.docling_url).await;
 
     // Collect files to process
     let exts: Vec<&str> = extensions.split(',').map(|s| s.trim()).collect();
@@ -113,16 +192,14 @@ pub async fn ingest(cli: &Cli, path: &str, extensions: &str) -> Result<()> {
         files.push(path_obj.to_path_buf());
     } else {
         let pattern = format!("{}/**/*", path);
-        for entry in glob(&pattern).context("Invalid glob pattern")? {
-            if let Ok(path_buf) = entry {
-                if path_buf.is_file() {
-                    let ext = path_buf
-                        .extension()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("");
-                    if exts.contains(&ext) {
-                        files.push(path_buf);
-                    }
+        for path_buf in glob(&pattern).context("Invalid glob pattern")?.flatten() {
+            if path_buf.is_file() {
+                let ext = path_buf
+                    .extension()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("");
+                if exts.contains(&ext) {
+                    files.push(path_buf);
                 }
             }
         }
@@ -140,7 +217,27 @@ pub async fn ingest(cli: &Cli, path: &str, extensions: &str) -> Result<()> {
     let vector_size = test_embedding.len();
 
     // Ensure collection exists
-    qdrant.ensure_collection(&cli.collection, vector_size).await?;
+    store
+        .ensure_collection(collection, vector_size, &config.storage(collection))
+        .await?;
+
+    // Record how the collection was built, and store the prompt template with
+    // it, so they travel with it
+    let mut metadata = store
+        .get_metadata(collection)
+        .await?
+        .unwrap_or_default();
+    if let Some(name) = &cli.prompt {
+        let template = PromptTemplate::load(name, &config)?;
+        metadata.prompt = Some(template.text().to_string());
+        println!("Storing prompt template '{}' with collection '{}'", name, collection);
+    }
+    metadata.embed_model = Some(backend.embed_model().to_string());
+    metadata.backend = Some(backend.name().to_string());
+    metadata.dimensions = Some(vector_size);
+    metadata.chunker = Some(CHUNKER.to_string());
+    metadata.chunk_size = Some(CHUNK_SIZE);
+    metadata.version = Some(env!("CARGO_PKG_VERSION").to_string());
 
     // Process files with progress bar
     let pb = ProgressBar::new(files.len() as u64);
@@ -152,128 +249,480 @@ pub async fn ingest(cli: &Cli, path: &str, extensions: &str) -> Result<()> {
     );
 
     let mut total_chunks = 0;
+    let mut skipped: Vec<(std::path::PathBuf, String)> = Vec::new();
+
+    // Near-duplicate tracking: fingerprints seen this run, and sources to merge per point
+    let mut seen: SimHashIndex<DocumentChunk> = SimHashIndex::new(dedup.dedup_distance);
+    let mut merged: HashMap<String, (String, Vec<String>)> = HashMap::new();
+    let mut duplicates = 0;
+    let mut summaries = 0;
+    let mut total_questions = 0;
 
     for file_path in files {
         pb.set_message(format!("Processing {}", file_path.display()));
 
         // Parse document
-        let content = if use_docling {
-            let ext = file_path.extension().and_then(|s| s.to_str()).unwrap_or("");
-            if ["pdf", "docx", "pptx", "xlsx", "html"].contains(&ext) {
-                match parse_with_docling(&cli.docling_url, &file_path).await {
-                    Ok(c) => c,
-                    Err(e) => {
-                        eprintln!("Warning: Failed to parse {} with docling: {}", file_path.display(), e);
-                        read_file_directly(&file_path).await.unwrap_or_default()
-                    }
-                }
-            } else {
-                read_file_directly(&file_path).await.unwrap_or_default()
+        let content = match parsers.parse(&file_path).await {
+            Ok(c) => c,
+            Err(e) => {
+                skipped.push((file_path, format!("{:#}", e)));
+                pb.inc(1);
+                continue;
             }
-        } else {
-            read_file_directly(&file_path).await.unwrap_or_default()
         };
 
-        if content.is_empty() {
+        if content.trim().is_empty() {
+            skipped.push((file_path, "no text content".to_string()));
             pb.inc(1);
             continue;
         }
 
-        // Chunk the content
+        // Chunk the content; every point from this file shares a document ID
         let chunks = chunk_text(&content);
+        let outline = DocumentOutline::new(&content);
+        let doc_id = uuid::Uuid::new_v4().to_string();
 
         // Create document chunks and embeddings
         let mut doc_chunks = Vec::new();
         let mut embeddings = Vec::new();
 
-        for chunk_content in chunks {
+        for (position, (offset, chunk_content)) in chunks.into_iter().enumerate() {
             let chunk = DocumentChunk {
                 id: uuid::Uuid::new_v4().to_string(),
-                content: chunk_content.clone(),
+                content: chunk_content,
                 source: file_path.to_string_lossy().to_string(),
+                kind: PointKind::Chunk,
+                parent_id: None,
+                question: None,
+                doc_id: Some(doc_id.clone()),
+                position: Some(position),
+                heading: outline.heading(offset),
+                page: outline.page(offset),
+                other_sources: Vec::new(),
+                ingested_at: None,
+            };
+            let hash = simhash(&chunk.content);
+
+            // Near-duplicate of a chunk seen earlier in this run
+            let mut original = seen.find(hash).cloned();
+
+            // No need to embed a duplicate we are about to drop
+            let embedding = if original.is_some() && dedup.duplicates != DuplicateMode::Keep {
+                None
+            } else {
+                match backend.embed(&chunk.content).await {
+                    Ok(embedding) => Some(embedding),
+                    Err(e) => {
+                        eprintln!("Warning: Failed to embed chunk: {}", e);
+                        continue;
+                    }
+                }
+            };
+
+            // Near-duplicate of a point already in the collection
+            if let (None, Some(threshold), Some(embedding)) =
+                (&original, dedup.dedup_similarity, &embedding)
+            {
+                let hits = store
+                    .search_scored(
+                        collection,
+                        embedding.clone(),
+                        1,
+                        &Filter::chunks(),
+                        false,
+                        search,
+                    )
+                    .await?;
+                original = hits
+                    .into_iter()
+                    .find(|hit| hit.score >= threshold)
+                    .map(|hit| hit.chunk);
+            }
+
+            match original {
+                Some(original) => {
+                    duplicates += 1;
+                    match dedup.duplicates {
+                        DuplicateMode::Skip => continue,
+                        DuplicateMode::Merge => {
+                            record_duplicate(&mut merged, &original, &chunk.source);
+                            continue;
+                        }
+                        DuplicateMode::Keep => {}
+                    }
+                }
+                None => seen.insert(
+                    hash,
+                    DocumentChunk {
+                        content: String::new(),
+                        ..chunk.clone()
+                    },
+                ),
+            }
+
+            let Some(embedding) = embedding else {
+                continue;
             };
 
-            match backend.embed(&chunk_content).await {
-                Ok(embedding) => {
-                    doc_chunks.push(chunk);
+            // Optional hypothetical questions, searched alongside the chunk itself
+            if questions > 0 {
+                match generate_questions(backend, &chunk, questions).await {
+                    Ok(points) => {
+                        total_questions += points.len();
+                        for (point, embedding) in points {
+                            doc_chunks.push(point);
+                            embeddings.push(embedding);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Warning: Failed to generate questions for chunk: {}", e);
+                    }
+                }
+            }
+
+            doc_chunks.push(chunk);
+            embeddings.push(embedding);
+        }
+
+        // Optional document-level summary, stored as its own point
+        if summarize {
+            let source = file_path.to_string_lossy();
+            match summarize_document(backend, &source, &doc_id, &content).await {
+                Ok((summary, embedding)) => {
+                    doc_chunks.push(summary);
                     embeddings.push(embedding);
+                    summaries += 1;
                 }
                 Err(e) => {
-                    eprintln!("Warning: Failed to embed chunk: {}", e);
+                    eprintln!("Warning: Failed to summarise {}: {}", file_path.display(), e);
                 }
             }
         }
 
-        // Batch upsert
+        // Batch upsert, stamping every point with the ingest time
+        let ingested_at = time::now();
+        for chunk in &mut doc_chunks {
+            chunk.ingested_at = Some(ingested_at.clone());
+        }
         if !doc_chunks.is_empty() {
-            qdrant
-                .upsert_batch(&cli.collection, &doc_chunks, embeddings)
+            store
+                .upsert_batch(collection, &doc_chunks, embeddings)
                 .await?;
-            total_chunks += doc_chunks.len();
+            total_chunks += doc_chunks
+                .iter()
+                .filter(|c| c.kind == PointKind::Chunk)
+                .count();
         }
 
         pb.inc(1);
     }
 
+    // Record merged duplicate sources on the chunks they duplicate
+    for (id, (source, other_sources)) in &merged {
+        if !other_sources.is_empty() {
+            store
+                .set_sources(collection, id, source, other_sources)
+                .await?;
+        }
+    }
+
     pb.finish_with_message("Done!");
-    println!("\nIngested {} chunks into collection '{}'", total_chunks, cli.collection);
+
+    metadata.last_ingest = Some(time::now());
+    store.set_metadata(collection, &metadata).await?;
+
+    println!("\nIngested {} chunks into collection '{}'", total_chunks, collection);
+
+    let action = match dedup.duplicates {
+        DuplicateMode::Keep => "kept",
+        DuplicateMode::Skip => "skipped",
+        DuplicateMode::Merge => "merged",
+    };
+    println!("Found {} near-duplicate chunks ({})", duplicates, action);
+    if summarize {
+        println!("Generated {} document summaries", summaries);
+    }
+    if questions > 0 {
+        println!("Generated {} hypothetical questions", total_questions);
+    }
+
+    if !skipped.is_empty() {
+        println!("\nSkipped {} files:", skipped.len());
+        for (path, reason) in &skipped {
+            println!("  - {}: {}", path.display(), reason);
+        }
+    }
 
     Ok(())
 }
 
+/// Milliseconds spent in each stage of a run
+#[derive(Serialize)]
+struct Timings {
+    retrieval: u64,
+    generation: u64,
+    verification: u64,
+    total: u64,
+}
+
+/// Everything a run produced, for structured output
+#[derive(Serialize)]
+struct RunOutput<'a> {
+    question: &'a str,
+    answer: String,
+    /// False when nothing relevant was found and the model wasn't asked
+    found: bool,
+    backend: &'a str,
+    model: &'a str,
+    embed_model: &'a str,
+    queries: Vec<String>,
+    chunks: Vec<ScoredChunk>,
+    /// Context blocks cited in the answer; `[n]` refers to `chunks[n - 1]`
+    citations: Vec<Citation>,
+    sources: Vec<String>,
+    /// Per-statement support check, with --verify
+    grounding: Option<Grounding>,
+    timings_ms: Timings,
+    usage: Option<TokenUsage>,
+}
+
+impl RunOutput<'_> {
+    /// The answer with unsupported statements marked, when verified
+    fn marked_answer(&self) -> String {
+        match &self.grounding {
+            Some(grounding) => grounding.mark(&self.answer),
+            None => self.answer.clone(),
+        }
+    }
+
+    /// Summary line for verified answers with unsupported statements
+    fn grounding_warning(&self) -> Option<String> {
+        let grounding = self.grounding.as_ref().filter(|g| g.unsupported > 0)?;
+        Some(format!(
+            "{} of {} statements are not supported by the sources and are marked [unsupported].",
+            grounding.unsupported,
+            grounding.statements.len()
+        ))
+    }
+
+    fn print(&self, format: OutputFormat) -> Result<()> {
+        match format {
+            OutputFormat::Text => {
+                println!("{}\n", self.marked_answer());
+                if !self.citations.is_empty() {
+                    for citation in &self.citations {
+                        println!("[{}] {}", citation.number, citation.label());
+                    }
+                } else if self.found {
+                    // The model didn't cite anything, so list everything it was given
+                    println!("Sources:");
+                    for source in &self.sources {
+                        println!("  - {}", source);
+                    }
+                }
+                if let Some(warning) = self.grounding_warning() {
+                    println!("\nWarning: {}", warning);
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(self)?),
+            OutputFormat::Jsonl => println!("{}", serde_json::to_string(self)?),
+            OutputFormat::Markdown => {
+                println!("# {}\n", self.question);
+                println!(
+                    "{}\n",
+                    markdown_footnotes(self.marked_answer().trim(), self.chunks.len())
+                );
+                if let Some(warning) = self.grounding_warning() {
+                    println!("> **Warning:** {}\n", warning);
+                }
+                if !self.citations.is_empty() {
+                    for citation in &self.citations {
+                        println!("[^{}]: {}", citation.number, citation.label());
+                    }
+                    println!();
+                }
+                if self.found {
+                    println!("## Sources\n");
+                    for (i, hit) in self.chunks.iter().enumerate() {
+                        println!("{}. {} (score {:.3})", i + 1, location(hit), hit.score);
+                    }
+                    println!();
+                }
+                println!(
+                    "*{} ({}) in {} ms*",
+                    self.model, self.backend, self.timings_ms.total
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Query the knowledge base
-pub async fn run(cli: &Cli, question: &str) -> Result<()> {
-    let qdrant = QdrantClient::new(Some(&cli.qdrant_url));
+pub async fn run(
+    cli: &Cli,
+    question: &str,
+    output: OutputFormat,
+    verify: &VerifyArgs,
+    retrieval: &RetrievalArgs,
+) -> Result<()> {
+    let started = Instant::now();
+    let store = store::create_store(cli).await?;
 
     // Check if collection has data
-    let info = qdrant.collection_info(&cli.collection).await?;
-    if info.is_none() || info.as_ref().map(|i| i.points_count).unwrap_or(0) == 0 {
+    if store.points_count(&cli.collection).await? == 0 {
+        if output != OutputFormat::Text {
+            anyhow::bail!("Knowledge base is empty. Run 'know ingest <path>' first.");
+        }
         println!("Knowledge base is empty. Run 'know ingest <path>' first.");
         return Ok(());
     }
 
-    // Create backend
-    let backend = create_backend(cli).await?;
+    // Pick the prompt template before doing any work, so a bad --prompt fails fast
+    // (the first collection's template is used when searching several)
+    let config = Config::load(cli.config.as_deref())?;
+    let primary = &cli.collection[0];
+    let stored = store.get_metadata(primary).await?;
+    let template = PromptTemplate::resolve(
+        cli.prompt.as_deref(),
+        &config,
+        primary,
+        stored.as_ref().and_then(|m| m.prompt.as_deref()),
+    )?;
+
+    // Create backends, one per embedding model used by the collections
+    let backends = CollectionBackends::new(
+        cli,
+        &config,
+        &*store,
+        cli.collection.iter().map(String::as_str),
+    )
+    .await?;
+    let backend = backends.default();
+
+    eprintln!("Thinking...\n");
 
-    println!("Thinking...\n");
+    // Search for relevant chunks
+    let options = retrieval.options(5);
+    let targets = Target::all(&backends, &*store, &cli.collection).await?;
+    let retrieved = retrieve(backend, &*store, &targets, question, &options).await?;
+    let retrieval_time = started.elapsed();
+
+    if cli.verbose && options.query_mode != QueryMode::Raw {
+        eprintln!("Search queries:");
+        for query in &retrieved.queries {
+            eprintln!("  - {}", query);
+        }
+        eprintln!();
+    }
 
-    // Embed the question
-    let query_embedding = backend.embed(question).await?;
+    let results = retrieved.hits;
 
-    // Search for relevant chunks
-    let results = qdrant.search(&cli.collection, query_embedding, 5).await?;
+    // Nothing relevant enough: answer without calling the model rather than
+    // let it guess from unrelated context
+    let mut generation_time = Duration::ZERO;
+    let mut verification_time = Duration::ZERO;
+    let (answer, usage, chunks, citations, grounding) = if results.is_empty() {
+        (cli.not_found_message.clone(), None, Vec::new(), Vec::new(), None)
+    } else {
+        // Build context from search results, fitted to the model's context window
+        let builder =
+            ContextBuilder::new(backend, cli.context_length, cli.answer_tokens).await;
+        let context = builder.build(question, results);
+
+        if cli.verbose {
+            eprintln!(
+                "Context: {} of {} tokens budgeted ({} chunks used, {} trimmed, {} dropped)\n",
+                crate::context::estimate_tokens(&context.text),
+                builder.budget(question),
+                context.hits.len(),
+                context.trimmed,
+                context.dropped
+            );
+        }
 
-    if results.is_empty() {
-        println!("No relevant documents found.");
-        return Ok(());
-    }
+        // Generate response
+        let generation_started = Instant::now();
+        let system = template.render(&PromptVars {
+            context: &context.text,
+            question,
+            collection: &cli.collection.join(", "),
+            history: "",
+        });
+        let completion = backend.chat(&system, question).await?;
+        generation_time = generation_started.elapsed();
+
+        // Check the model only cited blocks it was actually given
+        let cited = resolve(&completion.text, &context.hits);
+        if !cited.invalid.is_empty() {
+            eprintln!(
+                "Warning: Removed citations of blocks not in the context: {:?}",
+                cited.invalid
+            );
+        }
 
-    // Build context from search results
-    let context: String = results
-        .iter()
-        .map(|chunk| {
-            format!(
-                "[Source: {}]\n{}\n",
-                chunk.source,
-                chunk.content
-            )
-        })
-        .collect::<Vec<_>>()
-        .join("\n---\n");
-
-    // Generate response
-    let response = backend.generate(question, &context).await?;
-
-    println!("{}\n", response);
-
-    // Print sources
-    println!("Sources:");
-    let mut seen_sources = std::collections::HashSet::new();
-    for chunk in &results {
-        if seen_sources.insert(&chunk.source) {
-            println!("  - {}", chunk.source);
+        // Optionally check each statement against the chunks it was generated from
+        let grounding = match verify.verify {
+            Some(mode) => {
+                let verification_started = Instant::now();
+                let result = grounding::verify(
+                    backend,
+                    &cited.text,
+                    &context,
+                    mode,
+                    verify.verify_threshold,
+                )
+                .await
+                .context("Failed to verify answer")?;
+                verification_time = verification_started.elapsed();
+                Some(result)
+            }
+            None => None,
+        };
+
+        (
+            cited.text,
+            completion.usage,
+            context.hits,
+            cited.citations,
+            grounding,
+        )
+    };
+
+    let mut sources: Vec<String> = Vec::new();
+    for hit in &chunks {
+        let chunk = &hit.chunk;
+        for source in std::iter::once(&chunk.source).chain(&chunk.other_sources) {
+            let source = match &hit.collection {
+                Some(collection) => format!("[{}] {}", collection, source),
+                None => source.clone(),
+            };
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
         }
     }
 
-    Ok(())
+    let total_time = started.elapsed();
+    RunOutput {
+        question,
+        answer,
+        found: !chunks.is_empty(),
+        backend: backend.name(),
+        model: backend.model(),
+        embed_model: backend.embed_model(),
+        queries: retrieved.queries,
+        chunks,
+        citations,
+        sources,
+        grounding,
+        timings_ms: Timings {
+            retrieval: retrieval_time.as_millis() as u64,
+            generation: generation_time.as_millis() as u64,
+            verification: verification_time.as_millis() as u64,
+            total: total_time.as_millis() as u64,
+        },
+        usage,
+    }
+    .print(output)
 }
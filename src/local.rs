@@ -0,0 +1,974 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use crate::qdrant::{
+    CollectionInfo, CollectionMetadata, Distance, DocumentChunk, Filter, ScoredChunk, SearchParams,
+    StorageSettings,
+};
+use crate::store::{check_vector_size, VectorStore};
+
+/// Vector size, distance and metadata of a collection
+const COLLECTION_FILE: &str = "collection.json";
+
+/// Append-only log of the writes to a collection's points
+const POINTS_FILE: &str = "points.jsonl";
+
+/// The points log is rewritten once it holds this many times more entries
+/// than there are points
+const COMPACT_RATIO: usize = 4;
+
+/// Logs shorter than this are never worth rewriting
+const COMPACT_MIN_ENTRIES: usize = 1024;
+
+/// Lock file shared by every process using the store
+const LOCK_FILE: &str = "lock";
+
+/// Collections kept as files under a directory and searched exhaustively in
+/// process, so know can run without any services.
+///
+/// Several processes can share the directory: writes hold an exclusive
+/// advisory lock on it and reads a shared one, and a collection another
+/// process has written to is read again before it's used.
+pub struct LocalStore {
+    shared: Arc<StoreDir>,
+}
+
+/// The store's directory and the collections read from it so far, shared with
+/// the blocking tasks that read and write it
+struct StoreDir {
+    dir: PathBuf,
+    /// Collections read so far, by name
+    collections: Mutex<HashMap<String, Collection>>,
+}
+
+/// Sizes and modification times of a collection's files, to notice when
+/// another process has changed them
+#[derive(Clone, Copy, PartialEq)]
+struct Stamp {
+    file_modified: SystemTime,
+    log_len: u64,
+    log_modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    /// Stamp of the collection in `dir`, or None if it doesn't exist
+    fn of(dir: &Path) -> Result<Option<Self>> {
+        let Some(file) = file_metadata(&dir.join(COLLECTION_FILE))? else {
+            return Ok(None);
+        };
+        let log = file_metadata(&dir.join(POINTS_FILE))?;
+        Ok(Some(Self {
+            file_modified: file.modified()?,
+            log_len: log.as_ref().map_or(0, |log| log.len()),
+            log_modified: log.map(|log| log.modified()).transpose()?,
+        }))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CollectionFile {
+    vector_size: usize,
+    distance: Distance,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<CollectionMetadata>,
+}
+
+/// One line of the points log
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum LogEntry {
+    /// Insert or replace a point
+    Upsert {
+        id: String,
+        vector: Vec<f32>,
+        payload: serde_json::Value,
+    },
+    /// Remove points
+    Delete { ids: Vec<String> },
+    /// Merge fields into the payload of a point
+    Payload {
+        id: String,
+        payload: serde_json::Value,
+    },
+}
+
+#[derive(Clone)]
+struct StoredPoint {
+    vector: Vec<f32>,
+    payload: serde_json::Value,
+}
+
+struct Collection {
+    dir: PathBuf,
+    file: CollectionFile,
+    /// Points by ID, so scrolls come back in a stable order like Qdrant's
+    points: BTreeMap<String, StoredPoint>,
+    /// Lines in the points log, to tell when it's worth compacting
+    log_entries: usize,
+    /// The files as of the last read or write
+    stamp: Option<Stamp>,
+}
+
+impl Collection {
+    fn create(dir: PathBuf, file: CollectionFile) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let mut collection = Self {
+            dir,
+            file,
+            points: BTreeMap::new(),
+            log_entries: 0,
+            stamp: None,
+        };
+        collection.save_file()?;
+        Ok(collection)
+    }
+
+    /// Read a collection back, replaying its points log
+    fn load(dir: PathBuf) -> Result<Self> {
+        let stamp = Stamp::of(&dir)?;
+        let path = dir.join(COLLECTION_FILE);
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let file: CollectionFile = serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        let mut collection = Self {
+            dir,
+            file,
+            points: BTreeMap::new(),
+            log_entries: 0,
+            stamp,
+        };
+
+        let path = collection.dir.join(POINTS_FILE);
+        let log = match File::open(&path) {
+            Ok(log) => log,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(collection),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        for (i, line) in BufReader::new(log).lines().enumerate() {
+            let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // A write cut short leaves a partial last line, which is dropped
+            match serde_json::from_str(&line) {
+                Ok(entry) => collection.apply(entry),
+                Err(e) => eprintln!(
+                    "Warning: Skipping unreadable line {} of {}: {}",
+                    i + 1,
+                    path.display(),
+                    e
+                ),
+            }
+            collection.log_entries += 1;
+        }
+
+        Ok(collection)
+    }
+
+    fn save_file(&mut self) -> Result<()> {
+        let path = self.dir.join(COLLECTION_FILE);
+        let text = serde_json::to_string_pretty(&self.file)?;
+        std::fs::write(&path, text)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        self.stamp = Stamp::of(&self.dir)?;
+        Ok(())
+    }
+
+    fn apply(&mut self, entry: LogEntry) {
+        match entry {
+            LogEntry::Upsert {
+                id,
+                vector,
+                payload,
+            } => {
+                self.points.insert(id, StoredPoint { vector, payload });
+            }
+            LogEntry::Delete { ids } => {
+                for id in ids {
+                    self.points.remove(&id);
+                }
+            }
+            LogEntry::Payload { id, payload } => {
+                if let (Some(point), serde_json::Value::Object(fields)) =
+                    (self.points.get_mut(&id), payload)
+                {
+                    if let Some(existing) = point.payload.as_object_mut() {
+                        existing.extend(fields);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Write entries to the points log, then apply them
+    fn append(&mut self, entries: Vec<LogEntry>) -> Result<()> {
+        let path = self.dir.join(POINTS_FILE);
+        let log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut writer = BufWriter::new(log);
+        for entry in &entries {
+            serde_json::to_writer(&mut writer, entry)?;
+            writer.write_all(b"\n")?;
+        }
+        writer
+            .flush()
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        self.log_entries += entries.len();
+        for entry in entries {
+            self.apply(entry);
+        }
+        self.stamp = Stamp::of(&self.dir)?;
+
+        if self.log_entries > COMPACT_MIN_ENTRIES
+            && self.log_entries > self.points.len() * COMPACT_RATIO
+        {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the points log with one entry per live point
+    fn compact(&mut self) -> Result<()> {
+        let path = self.dir.join(POINTS_FILE);
+        let temp = self.dir.join(format!("{}.tmp", POINTS_FILE));
+        let mut writer = BufWriter::new(
+            File::create(&temp).with_context(|| format!("Failed to create {}", temp.display()))?,
+        );
+        for (id, point) in &self.points {
+            serde_json::to_writer(
+                &mut writer,
+                &serde_json::json!({
+                    "op": "upsert",
+                    "id": id,
+                    "vector": point.vector,
+                    "payload": point.payload,
+                }),
+            )?;
+            writer.write_all(b"\n")?;
+        }
+        writer
+            .flush()
+            .with_context(|| format!("Failed to write {}", temp.display()))?;
+        drop(writer);
+
+        std::fs::rename(&temp, &path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        self.log_entries = self.points.len();
+        self.stamp = Stamp::of(&self.dir)?;
+        Ok(())
+    }
+
+    fn matching<'a>(
+        &'a self,
+        filter: &'a Filter,
+    ) -> impl Iterator<Item = (&'a String, &'a StoredPoint)> + 'a {
+        self.points
+            .iter()
+            .filter(|(_, point)| filter.is_met_by(&point.payload))
+    }
+
+    /// Similarity of two vectors, higher being closer; distances are turned
+    /// into similarities like Qdrant scores are
+    fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        let score = match self.file.distance {
+            Distance::Cosine | Distance::Dot => a.iter().zip(b).map(|(x, y)| x * y).sum(),
+            Distance::Euclid => a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f32>()
+                .sqrt(),
+            Distance::Manhattan => a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum(),
+        };
+        self.file.distance.similarity(score)
+    }
+
+    /// Normalise vectors of cosine collections, which are then scored by dot product
+    fn prepare(&self, mut vector: Vec<f32>) -> Vec<f32> {
+        if self.file.distance == Distance::Cosine {
+            let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                vector.iter_mut().for_each(|x| *x /= norm);
+            }
+        }
+        vector
+    }
+
+    fn info(&self) -> CollectionInfo {
+        CollectionInfo {
+            points_count: self.points.len(),
+            indexed_vectors_count: self.points.len(),
+            vector_size: Some(self.file.vector_size),
+            distance: Some(self.file.distance.as_str().to_string()),
+            hnsw_m: None,
+            hnsw_ef_construct: None,
+            quantization: None,
+            on_disk: false,
+            on_disk_payload: false,
+            shards: None,
+            config: serde_json::json!({
+                "params": {
+                    "vectors": {
+                        "size": self.file.vector_size,
+                        "distance": self.file.distance.as_str(),
+                    }
+                }
+            }),
+        }
+    }
+}
+
+impl LocalStore {
+    /// Open the store kept under `dir`, which is created on first write
+    pub fn open(dir: PathBuf) -> Result<Self> {
+        Ok(Self {
+            shared: Arc::new(StoreDir {
+                dir,
+                collections: Mutex::new(HashMap::new()),
+            }),
+        })
+    }
+
+    /// Run `op` with the store locked against other processes (exclusively
+    /// for writes) and the collections read so far. It runs on a blocking
+    /// thread, since waiting for another process's lock and the file I/O
+    /// mustn't hold up the async runtime.
+    async fn with_store<T, F>(&self, exclusive: bool, op: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&StoreDir, &mut HashMap<String, Collection>) -> Result<T> + Send + 'static,
+    {
+        let shared = self.shared.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut cache = shared
+                .collections
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let _lock = shared.lock(exclusive)?;
+            op(&shared, &mut cache)
+        })
+        .await
+        .context("Local store task failed")?
+    }
+
+    async fn create(&self, collection: &str, file: CollectionFile) -> Result<()> {
+        let collection = collection.to_string();
+        self.with_store(true, move |store, cache| {
+            if store.loaded(cache, &collection)?.is_some() {
+                anyhow::bail!("Collection '{}' already exists", collection);
+            }
+            let created = Collection::create(store.collection_dir(&collection)?, file)?;
+            cache.insert(collection, created);
+            Ok(())
+        })
+        .await
+    }
+}
+
+impl StoreDir {
+    fn collections_dir(&self) -> PathBuf {
+        self.dir.join("collections")
+    }
+
+    fn collection_dir(&self, collection: &str) -> Result<PathBuf> {
+        if collection.is_empty()
+            || collection == "."
+            || collection == ".."
+            || collection.contains(['/', '\\'])
+        {
+            anyhow::bail!("Invalid collection name '{}'", collection);
+        }
+        Ok(self.collections_dir().join(collection))
+    }
+
+    /// Lock the store against other processes until the returned file is
+    /// dropped: shared for reading, exclusive for writing. There is nothing to
+    /// lock when reading a store that hasn't been written yet.
+    fn lock(&self, exclusive: bool) -> Result<Option<File>> {
+        if !exclusive && !self.dir.exists() {
+            return Ok(None);
+        }
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self.dir.join(LOCK_FILE);
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        if exclusive {
+            file.lock()
+        } else {
+            file.lock_shared()
+        }
+        .with_context(|| format!("Failed to lock {}", path.display()))?;
+        Ok(Some(file))
+    }
+
+    /// The collection, read from disk when it's first used and again whenever
+    /// another process has changed it; callers hold the store lock
+    fn loaded<'a>(
+        &self,
+        cache: &'a mut HashMap<String, Collection>,
+        collection: &str,
+    ) -> Result<Option<&'a mut Collection>> {
+        let dir = self.collection_dir(collection)?;
+        let Some(stamp) = Stamp::of(&dir)? else {
+            cache.remove(collection);
+            return Ok(None);
+        };
+        if cache.get(collection).is_none_or(|c| c.stamp != Some(stamp)) {
+            cache.insert(collection.to_string(), Collection::load(dir)?);
+        }
+        Ok(cache.get_mut(collection))
+    }
+
+    fn existing<'a>(
+        &self,
+        cache: &'a mut HashMap<String, Collection>,
+        collection: &str,
+    ) -> Result<&'a mut Collection> {
+        self.loaded(cache, collection)?
+            .with_context(|| format!("Collection '{}' not found", collection))
+    }
+}
+
+#[async_trait]
+impl VectorStore for LocalStore {
+    async fn is_available(&self) -> bool {
+        true
+    }
+
+    async fn ensure_collection(
+        &self,
+        collection: &str,
+        vector_size: usize,
+        settings: &StorageSettings,
+    ) -> Result<()> {
+        if let Some(info) = self.collection_info(collection).await? {
+            if let Some(existing) = info.vector_size {
+                check_vector_size(collection, existing, vector_size)?;
+            }
+            return Ok(());
+        }
+
+        let only_distance = StorageSettings {
+            distance: settings.distance,
+            ..Default::default()
+        };
+        if *settings != only_distance {
+            eprintln!(
+                "Warning: The local store only supports the distance setting; \
+                the other storage settings of '{}' are ignored.",
+                collection
+            );
+        }
+
+        self.create(
+            collection,
+            CollectionFile {
+                vector_size,
+                distance: settings.distance.unwrap_or_default(),
+                metadata: None,
+            },
+        )
+        .await
+    }
+
+    async fn create_collection_like(
+        &self,
+        collection: &str,
+        like: &CollectionInfo,
+        vector_size: usize,
+    ) -> Result<()> {
+        let distance = like
+            .distance
+            .as_deref()
+            .and_then(|d| Distance::from_str(d, true).ok())
+            .unwrap_or_default();
+        self.create(
+            collection,
+            CollectionFile {
+                vector_size,
+                distance,
+                metadata: None,
+            },
+        )
+        .await
+    }
+
+    async fn upsert_batch(
+        &self,
+        collection: &str,
+        chunks: &[DocumentChunk],
+        embeddings: Vec<Vec<f32>>,
+    ) -> Result<()> {
+        let collection = collection.to_string();
+        let points: Vec<(String, serde_json::Value)> = chunks
+            .iter()
+            .map(|chunk| (chunk.id.clone(), chunk.payload()))
+            .collect();
+        self.with_store(true, move |store, cache| {
+            let target = store.existing(cache, &collection)?;
+
+            let mut entries = Vec::with_capacity(points.len());
+            for ((id, payload), embedding) in points.into_iter().zip(embeddings) {
+                check_vector_size(&collection, target.file.vector_size, embedding.len())?;
+                entries.push(LogEntry::Upsert {
+                    id,
+                    vector: target.prepare(embedding),
+                    payload,
+                });
+            }
+            target.append(entries)
+        })
+        .await
+    }
+
+    /// Exact search over every point; HNSW and quantization parameters don't apply
+    async fn search_scored(
+        &self,
+        collection: &str,
+        query_embedding: Vec<f32>,
+        limit: usize,
+        filter: &Filter,
+        with_vector: bool,
+        _params: &SearchParams,
+    ) -> Result<Vec<ScoredChunk>> {
+        let (collection, filter) = (collection.to_string(), filter.clone());
+        self.with_store(false, move |store, cache| {
+            let target = store.existing(cache, &collection)?;
+            check_vector_size(&collection, target.file.vector_size, query_embedding.len())?;
+            let query = target.prepare(query_embedding);
+
+            let mut scored: Vec<(f32, &String, &StoredPoint)> = target
+                .matching(&filter)
+                .map(|(id, point)| (target.score(&query, &point.vector), id, point))
+                .collect();
+            scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+            scored
+                .into_iter()
+                .take(limit)
+                .map(|(score, id, point)| {
+                    Ok(ScoredChunk {
+                        chunk: DocumentChunk::from_payload(id.clone(), point.payload.clone())?,
+                        score,
+                        vector: with_vector.then(|| point.vector.clone()),
+                        collection: None,
+                        fused_score: None,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn scroll(&self, collection: &str, filter: &Filter) -> Result<Vec<DocumentChunk>> {
+        let (collection, filter) = (collection.to_string(), filter.clone());
+        self.with_store(false, move |store, cache| {
+            store
+                .existing(cache, &collection)?
+                .matching(&filter)
+                .map(|(id, point)| DocumentChunk::from_payload(id.clone(), point.payload.clone()))
+                .collect()
+        })
+        .await
+    }
+
+    async fn scroll_fields(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        fields: &[&str],
+    ) -> Result<Vec<serde_json::Value>> {
+        let (collection, filter) = (collection.to_string(), filter.clone());
+        let fields: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+        self.with_store(false, move |store, cache| {
+            let payloads = store
+                .existing(cache, &collection)?
+                .matching(&filter)
+                .map(|(_, point)| {
+                    fields
+                        .iter()
+                        .filter_map(|field| {
+                            point
+                                .payload
+                                .get(field)
+                                .map(|value| (field.clone(), value.clone()))
+                        })
+                        .collect::<serde_json::Map<_, _>>()
+                        .into()
+                })
+                .collect();
+            Ok(payloads)
+        })
+        .await
+    }
+
+    async fn vectors(&self, collection: &str, ids: &[String]) -> Result<HashMap<String, Vec<f32>>> {
+        let (collection, ids) = (collection.to_string(), ids.to_vec());
+        self.with_store(false, move |store, cache| {
+            let target = store.existing(cache, &collection)?;
+            Ok(ids
+                .into_iter()
+                .filter_map(|id| {
+                    let vector = target.points.get(&id)?.vector.clone();
+                    Some((id, vector))
+                })
+                .collect())
+        })
+        .await
+    }
+
+    async fn set_sources(
+        &self,
+        collection: &str,
+        id: &str,
+        source: &str,
+        other_sources: &[String],
+    ) -> Result<()> {
+        let (collection, id) = (collection.to_string(), id.to_string());
+        let payload = serde_json::json!({ "source": source, "other_sources": other_sources });
+        self.with_store(true, move |store, cache| {
+            let target = store.existing(cache, &collection)?;
+            if !target.points.contains_key(&id) {
+                anyhow::bail!("Point '{}' not found in collection '{}'", id, collection);
+            }
+            target.append(vec![LogEntry::Payload { id, payload }])
+        })
+        .await
+    }
+
+    async fn delete_points(&self, collection: &str, filter: &Filter) -> Result<()> {
+        let (collection, filter) = (collection.to_string(), filter.clone());
+        self.with_store(true, move |store, cache| {
+            let target = store.existing(cache, &collection)?;
+            let ids: Vec<String> = target.matching(&filter).map(|(id, _)| id.clone()).collect();
+            if ids.is_empty() {
+                return Ok(());
+            }
+            target.append(vec![LogEntry::Delete { ids }])
+        })
+        .await
+    }
+
+    async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>> {
+        let collection = collection.to_string();
+        self.with_store(false, move |store, cache| {
+            Ok(store.loaded(cache, &collection)?.map(|c| c.info()))
+        })
+        .await
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        self.with_store(false, |store, _| {
+            let dir = store.collections_dir();
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to read {}", dir.display()))
+                }
+            };
+
+            let mut names = Vec::new();
+            for entry in entries {
+                let entry = entry.with_context(|| format!("Failed to read {}", dir.display()))?;
+                if entry.path().join(COLLECTION_FILE).exists() {
+                    names.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+            names.sort();
+            Ok(names)
+        })
+        .await
+    }
+
+    async fn copy_collection(&self, from: &str, to: &str) -> Result<usize> {
+        let (from, to) = (from.to_string(), to.to_string());
+        self.with_store(true, move |store, cache| {
+            if store.loaded(cache, &to)?.is_some() {
+                anyhow::bail!("Collection '{}' already exists", to);
+            }
+            let source = store.existing(cache, &from)?;
+
+            let mut copy = Collection::create(store.collection_dir(&to)?, source.file.clone())?;
+            copy.points = source.points.clone();
+            copy.compact()?;
+
+            let copied = copy.points.len();
+            cache.insert(to, copy);
+            Ok(copied)
+        })
+        .await
+    }
+
+    /// Rename by moving the collection's directory
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<usize> {
+        let (from, to) = (from.to_string(), to.to_string());
+        self.with_store(true, move |store, cache| {
+            if store.loaded(cache, &to)?.is_some() {
+                anyhow::bail!("Collection '{}' already exists", to);
+            }
+            let renamed = store.existing(cache, &from)?.points.len();
+            cache.remove(&from);
+
+            let (source, target) = (store.collection_dir(&from)?, store.collection_dir(&to)?);
+            std::fs::rename(&source, &target).with_context(|| {
+                format!(
+                    "Failed to move {} to {}",
+                    source.display(),
+                    target.display()
+                )
+            })?;
+            Ok(renamed)
+        })
+        .await
+    }
+
+    async fn delete_collection(&self, collection: &str) -> Result<()> {
+        let collection = collection.to_string();
+        self.with_store(true, move |store, cache| {
+            cache.remove(&collection);
+            remove_dir(&store.collection_dir(&collection)?)
+        })
+        .await
+    }
+
+    async fn get_metadata(&self, collection: &str) -> Result<Option<CollectionMetadata>> {
+        let collection = collection.to_string();
+        self.with_store(false, move |store, cache| {
+            Ok(store
+                .loaded(cache, &collection)?
+                .and_then(|c| c.file.metadata.clone()))
+        })
+        .await
+    }
+
+    async fn set_metadata(&self, collection: &str, metadata: &CollectionMetadata) -> Result<()> {
+        let (collection, metadata) = (collection.to_string(), metadata.clone());
+        self.with_store(true, move |store, cache| {
+            let target = store.existing(cache, &collection)?;
+            target.file.metadata = Some(metadata);
+            target.save_file()
+        })
+        .await
+    }
+}
+
+/// Size and times of a file, or None if it doesn't exist
+fn file_metadata(path: &Path) -> Result<Option<std::fs::Metadata>> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Remove a directory and everything in it, if it exists
+fn remove_dir(dir: &Path) -> Result<()> {
+    match std::fs::remove_dir_all(dir) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to delete {}", dir.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qdrant::Condition;
+
+    /// A store in a fresh temporary directory, removed when dropped
+    struct TempStore {
+        dir: PathBuf,
+    }
+
+    impl TempStore {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("know-local-{}", uuid::Uuid::new_v4()));
+            Self { dir }
+        }
+
+        /// A store over the directory, as another process would open it
+        fn open(&self) -> LocalStore {
+            LocalStore::open(self.dir.clone()).unwrap()
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            let _ = remove_dir(&self.dir);
+        }
+    }
+
+    fn chunk(id: &str, source: &str) -> DocumentChunk {
+        DocumentChunk::from_payload(
+            id.to_string(),
+            serde_json::json!({ "content": format!("text of {}", id), "source": source }),
+        )
+        .unwrap()
+    }
+
+    fn settings() -> StorageSettings {
+        StorageSettings {
+            distance: Some(Distance::Dot),
+            ..Default::default()
+        }
+    }
+
+    fn log_lines(temp: &TempStore, collection: &str) -> usize {
+        let path = temp
+            .dir
+            .join("collections")
+            .join(collection)
+            .join(POINTS_FILE);
+        std::fs::read_to_string(path).unwrap().lines().count()
+    }
+
+    #[tokio::test]
+    async fn log_replays_every_kind_of_write() {
+        let temp = TempStore::new();
+        let store = temp.open();
+        store
+            .ensure_collection("docs", 2, &settings())
+            .await
+            .unwrap();
+        store
+            .upsert_batch(
+                "docs",
+                &[
+                    chunk("a", "one.md"),
+                    chunk("b", "two.md"),
+                    chunk("c", "two.md"),
+                ],
+                vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.5, 0.5]],
+            )
+            .await
+            .unwrap();
+        store
+            .set_sources("docs", "a", "one.md", &["three.md".to_string()])
+            .await
+            .unwrap();
+        store
+            .delete_points(
+                "docs",
+                &Filter::default().with(Condition::matches("source", "two.md")),
+            )
+            .await
+            .unwrap();
+        let metadata = CollectionMetadata {
+            embed_model: Some("model".to_string()),
+            ..Default::default()
+        };
+        store.set_metadata("docs", &metadata).await.unwrap();
+
+        let reopened = temp.open();
+        let points = reopened.scroll("docs", &Filter::default()).await.unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].id, "a");
+        assert_eq!(points[0].other_sources, ["three.md"]);
+        let vectors = reopened.vectors("docs", &["a".to_string()]).await.unwrap();
+        assert_eq!(vectors["a"], [1.0, 0.0]);
+        let stored = reopened.get_metadata("docs").await.unwrap().unwrap();
+        assert_eq!(stored.embed_model.as_deref(), Some("model"));
+        // Three upserts, a payload update and a delete
+        assert_eq!(log_lines(&temp, "docs"), 5);
+    }
+
+    #[tokio::test]
+    async fn compaction_keeps_the_latest_points() {
+        let temp = TempStore::new();
+        let store = temp.open();
+        store
+            .ensure_collection("docs", 2, &settings())
+            .await
+            .unwrap();
+
+        let rounds = COMPACT_MIN_ENTRIES / 2 + 1;
+        let chunks: Vec<_> = (0..rounds)
+            .flat_map(|_| [chunk("a", "one.md"), chunk("b", "two.md")])
+            .collect();
+        let embeddings = (0..rounds)
+            .flat_map(|i| [vec![i as f32, 0.0], vec![0.0, i as f32]])
+            .collect();
+        store
+            .upsert_batch("docs", &chunks, embeddings)
+            .await
+            .unwrap();
+        assert_eq!(log_lines(&temp, "docs"), 2);
+
+        let latest = (rounds - 1) as f32;
+        let reopened = temp.open();
+        let ids = ["a".to_string(), "b".to_string()];
+        let vectors = reopened.vectors("docs", &ids).await.unwrap();
+        assert_eq!(vectors["a"], [latest, 0.0]);
+        assert_eq!(vectors["b"], [0.0, latest]);
+    }
+
+    #[tokio::test]
+    async fn stores_see_each_others_writes() {
+        let temp = TempStore::new();
+        let (first, second) = (temp.open(), temp.open());
+        first
+            .ensure_collection("docs", 2, &settings())
+            .await
+            .unwrap();
+        first
+            .upsert_batch("docs", &[chunk("a", "one.md")], vec![vec![1.0, 0.0]])
+            .await
+            .unwrap();
+
+        // The second store caches the collection, then the first writes to it
+        assert_eq!(
+            second
+                .scroll("docs", &Filter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        first
+            .upsert_batch("docs", &[chunk("b", "two.md")], vec![vec![0.0, 1.0]])
+            .await
+            .unwrap();
+        assert_eq!(
+            second
+                .scroll("docs", &Filter::default())
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // Writes build on the latest state, so neither store loses the other's points
+        second
+            .upsert_batch("docs", &[chunk("c", "three.md")], vec![vec![0.5, 0.5]])
+            .await
+            .unwrap();
+        let info = first.collection_info("docs").await.unwrap().unwrap();
+        assert_eq!(info.points_count, 3);
+
+        first.delete_collection("docs").await.unwrap();
+        assert!(second.collection_info("docs").await.unwrap().is_none());
+    }
+}
@@ -2,12 +2,30 @@
 // Copyright 2024 The Know Authors
 
 mod backend;
+mod citations;
 mod cli;
+mod collections;
+mod config;
+mod context;
+mod dedup;
 mod docker;
+mod docs;
+mod encoding;
+mod grounding;
 mod ingest;
+mod local;
+mod parser;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod prompt;
 mod qdrant;
+mod reembed;
 mod registry;
+mod retrieval;
+mod search;
 mod server;
+mod store;
+mod time;
 
 use anyhow::Result;
 use clap::Parser;
@@ -18,21 +36,48 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match &cli.command {
-        Commands::Run { query } => {
-            docker::ensure_running().await?;
+        Commands::Run {
+            query,
+            output,
+            verify,
+            retrieval,
+        } => {
+            store::ensure_running(&cli).await?;
             let question = query.join(" ");
-            ingest::run(&cli, &question).await
+            ingest::run(&cli, &question, *output, verify, retrieval).await
         }
-        Commands::Ingest { path, extensions } => {
-            docker::ensure_running().await?;
-            ingest::ingest(&cli, path, extensions).await
+        Commands::Search {
+            query,
+            top_k,
+            json,
+            retrieval,
+        } => {
+            store::ensure_running(&cli).await?;
+            let query = query.join(" ");
+            search::search(&cli, &query, *top_k, *json, retrieval).await
         }
-        Commands::Serve { port } => {
-            docker::ensure_running().await?;
-            server::serve(&cli, *port).await
+        Commands::Ingest {
+            path,
+            extensions,
+            dedup,
+            summarize,
+            questions,
+        } => {
+            store::ensure_running(&cli).await?;
+            ingest::ingest(&cli, path, extensions, dedup, *summarize, *questions).await
+        }
+        Commands::Serve { port, min_score } => {
+            store::ensure_running(&cli).await?;
+            server::serve(&cli, *port, *min_score).await
         }
         Commands::Down => docker::down().await,
-        Commands::Clean { collection } => qdrant::clean(collection).await,
+        Commands::Clean { collection } => store::clean(&cli, collection).await,
+        Commands::Docs { command } => docs::docs(&cli, command).await,
+        Commands::Reembed { to } => {
+            store::ensure_running(&cli).await?;
+            reembed::reembed(&cli, to.as_deref()).await
+        }
+        Commands::Collections { command } => collections::collections(&cli, command).await,
         Commands::Push { name } => registry::push(name).await,
         Commands::Pull { name } => registry::pull(name).await,
         Commands::Status => docker::status().await,
@@ -0,0 +1,470 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::encoding;
+
+/// Trait for document parsers that turn a file into plain text or markdown
+#[async_trait]
+pub trait DocumentParser: Send + Sync {
+    /// MIME types this parser understands
+    fn mime_types(&self) -> &'static [&'static str];
+    async fn parse(&self, path: &Path, data: &[u8]) -> Result<String>;
+    fn name(&self) -> &'static str;
+}
+
+/// Map a file extension to a MIME type
+fn mime_from_extension(ext: &str) -> Option<&'static str> {
+    let mime = match ext.to_ascii_lowercase().as_str() {
+        "pdf" => "application/pdf",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "html" | "htm" | "xhtml" => "text/html",
+        "md" | "markdown" => "text/markdown",
+        "txt" | "text" | "rst" | "adoc" | "csv" | "log" => "text/plain",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+/// Sniff a MIME type from the leading bytes of a file
+fn sniff_mime(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"%PDF-") {
+        return Some("application/pdf");
+    }
+
+    let head = &data[..data.len().min(512)];
+    let head = String::from_utf8_lossy(head)
+        .trim_start()
+        .to_ascii_lowercase();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        return Some("text/html");
+    }
+
+    None
+}
+
+/// Detect the MIME type of a file, preferring content sniffing over the extension
+pub fn detect_mime(path: &Path, data: &[u8]) -> &'static str {
+    sniff_mime(data)
+        .or_else(|| {
+            path.extension()
+                .and_then(|s| s.to_str())
+                .and_then(mime_from_extension)
+        })
+        .unwrap_or("text/plain")
+}
+
+/// Parser backed by the docling service
+pub struct DoclingParser {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl DoclingParser {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.to_string(),
+        }
+    }
+
+    /// Check if docling service is available
+    pub async fn is_available(&self) -> bool {
+        self.client
+            .get(format!("{}/health", self.base_url))
+            .send()
+            .await
+            .map(|r| r.status().is_success())
+            .unwrap_or(false)
+    }
+}
+
+#[async_trait]
+impl DocumentParser for DoclingParser {
+    fn mime_types(&self) -> &'static [&'static str] {
+        &[
+            "application/pdf",
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            "text/html",
+        ]
+    }
+
+    async fn parse(&self, path: &Path, data: &[u8]) -> Result<String> {
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("document");
+
+        // Create multipart form
+        let part = reqwest::multipart::Part::bytes(data.to_vec())
+            .file_name(file_name.to_string())
+            .mime_str("application/octet-stream")?;
+
+        let form = reqwest::multipart::Form::new().part("files", part);
+
+        #[derive(Deserialize)]
+        struct DoclingResponse {
+            document: DoclingDocument,
+        }
+
+        #[derive(Deserialize)]
+        struct DoclingDocument {
+            md_content: String,
+        }
+
+        let response = self
+            .client
+            .post(format!("{}/v1/convert/file", self.base_url))
+            .multipart(form)
+            .send()
+            .await
+            .context("Failed to connect to docling")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("Docling returned error {}: {}", status, text);
+        }
+
+        let result: DoclingResponse = response
+            .json()
+            .await
+            .context("Failed to parse docling response")?;
+
+        Ok(result.document.md_content)
+    }
+
+    fn name(&self) -> &'static str {
+        "docling"
+    }
+}
+
+/// Plain text and markdown parser
+pub struct TextParser;
+
+#[async_trait]
+impl DocumentParser for TextParser {
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["text/plain", "text/markdown"]
+    }
+
+    async fn parse(&self, _path: &Path, data: &[u8]) -> Result<String> {
+        encoding::decode(data)
+    }
+
+    fn name(&self) -> &'static str {
+        "text"
+    }
+}
+
+/// Elements whose content is never part of the readable text
+const HTML_SKIP_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "iframe", "form", "button", "nav", "header",
+    "footer", "aside",
+];
+
+/// Class or id fragments that mark page chrome rather than content
+const HTML_SKIP_HINTS: &[&str] = &[
+    "nav",
+    "navbar",
+    "menu",
+    "sidebar",
+    "footer",
+    "cookie",
+    "cookies",
+    "banner",
+    "advert",
+    "ads",
+    "breadcrumb",
+    "breadcrumbs",
+    "share",
+    "social",
+    "comments",
+];
+
+const HTML_BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "table",
+    "tr",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "blockquote",
+    "figure",
+    "figcaption",
+    "hr",
+];
+
+/// Pure-Rust HTML parser with readability-style main content extraction
+pub struct HtmlParser;
+
+impl HtmlParser {
+    fn is_chrome(element: &ElementRef) -> bool {
+        let value = element.value();
+        if HTML_SKIP_TAGS.contains(&value.name()) {
+            return true;
+        }
+        if value.attr("aria-hidden") == Some("true") || value.attr("hidden").is_some() {
+            return true;
+        }
+        let hints = format!(
+            "{} {}",
+            value.attr("class").unwrap_or(""),
+            value.attr("id").unwrap_or("")
+        )
+        .to_ascii_lowercase();
+        hints
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|word| HTML_SKIP_HINTS.contains(&word))
+    }
+
+    fn walk(element: ElementRef, out: &mut String, preformatted: bool) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => {
+                    if preformatted {
+                        out.push_str(text);
+                    } else {
+                        let words: Vec<&str> = text.split_whitespace().collect();
+                        if words.is_empty() {
+                            if !text.is_empty() && !out.is_empty() && !out.ends_with([' ', '\n']) {
+                                out.push(' ');
+                            }
+                            continue;
+                        }
+                        if text.starts_with(char::is_whitespace) && !out.ends_with([' ', '\n']) {
+                            out.push(' ');
+                        }
+                        out.push_str(&words.join(" "));
+                        if text.ends_with(char::is_whitespace) {
+                            out.push(' ');
+                        }
+                    }
+                }
+                Node::Element(_) => {
+                    let Some(child) = ElementRef::wrap(child) else {
+                        continue;
+                    };
+                    if Self::is_chrome(&child) {
+                        continue;
+                    }
+                    Self::walk_element(child, out, preformatted);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn walk_element(element: ElementRef, out: &mut String, preformatted: bool) {
+        let name = element.value().name();
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                Self::start_block(out);
+                out.push_str(&"#".repeat(level));
+                out.push(' ');
+                Self::walk(element, out, false);
+                Self::start_block(out);
+            }
+            "li" => {
+                Self::start_line(out);
+                out.push_str("- ");
+                Self::walk(element, out, preformatted);
+                Self::start_line(out);
+            }
+            "pre" => {
+                Self::start_block(out);
+                out.push_str("```\n");
+                Self::walk(element, out, true);
+                Self::start_line(out);
+                out.push_str("```");
+                Self::start_block(out);
+            }
+            "br" => out.push('\n'),
+            "td" | "th" => {
+                Self::walk(element, out, preformatted);
+                out.push_str(" | ");
+            }
+            _ if HTML_BLOCK_TAGS.contains(&name) => {
+                Self::start_block(out);
+                Self::walk(element, out, preformatted);
+                Self::start_block(out);
+            }
+            _ => Self::walk(element, out, preformatted),
+        }
+    }
+
+    fn start_line(out: &mut String) {
+        let trimmed = out.trim_end_matches(' ').len();
+        out.truncate(trimmed);
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+
+    fn start_block(out: &mut String) {
+        Self::start_line(out);
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+    }
+
+    /// Extract the readable text of an HTML document as lightweight markdown
+    pub fn extract(html: &str) -> String {
+        let document = Html::parse_document(html);
+
+        // Prefer an explicit main content container, falling back to the body
+        let root = ["article", "main", "[role=main]", "body"]
+            .iter()
+            .filter_map(|s| Selector::parse(s).ok())
+            .find_map(|selector| document.select(&selector).next())
+            .unwrap_or_else(|| document.root_element());
+
+        let mut out = String::new();
+
+        let has_h1 = Selector::parse("h1")
+            .map(|s| root.select(&s).next().is_some())
+            .unwrap_or(false);
+        if !has_h1 {
+            let title = Selector::parse("title")
+                .ok()
+                .and_then(|s| document.select(&s).next())
+                .map(|t| t.text().collect::<String>())
+                .unwrap_or_default();
+            let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !title.is_empty() {
+                out.push_str("# ");
+                out.push_str(&title);
+                out.push_str("\n\n");
+            }
+        }
+
+        Self::walk(root, &mut out, false);
+
+        out.lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim()
+            .to_string()
+    }
+}
+
+#[async_trait]
+impl DocumentParser for HtmlParser {
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["text/html"]
+    }
+
+    async fn parse(&self, _path: &Path, data: &[u8]) -> Result<String> {
+        let html = encoding::decode(data)?;
+        Ok(Self::extract(&html))
+    }
+
+    fn name(&self) -> &'static str {
+        "html"
+    }
+}
+
+/// Pure-Rust PDF text extraction
+pub struct PdfParser;
+
+#[async_trait]
+impl DocumentParser for PdfParser {
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["application/pdf"]
+    }
+
+    async fn parse(&self, _path: &Path, data: &[u8]) -> Result<String> {
+        let data = data.to_vec();
+
+        // Extraction is CPU bound and may panic on malformed files, so keep it off the runtime
+        let pages =
+            tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem_by_pages(&data))
+                .await
+                .context("PDF extraction panicked")?
+                .context("Failed to extract PDF text")?;
+
+        // Form feeds mark page breaks so chunks can record their page
+        Ok(pages.join("\x0c"))
+    }
+
+    fn name(&self) -> &'static str {
+        "pdf"
+    }
+}
+
+/// Ordered set of parsers; earlier parsers are preferred for a MIME type
+pub struct ParserRegistry {
+    parsers: Vec<Box<dyn DocumentParser>>,
+}
+
+impl ParserRegistry {
+    /// Build the default registry, preferring docling when it is reachable
+    pub async fn new(docling_url: &str) -> Self {
+        let mut parsers: Vec<Box<dyn DocumentParser>> = Vec::new();
+
+        let docling = DoclingParser::new(docling_url);
+        if docling.is_available().await {
+            parsers.push(Box::new(docling));
+        } else {
+            eprintln!(
+                "Warning: Docling not available at {}. Using built-in parsers for PDF, HTML and text.",
+                docling_url
+            );
+        }
+
+        parsers.push(Box::new(HtmlParser));
+        parsers.push(Box::new(PdfParser));
+        parsers.push(Box::new(TextParser));
+
+        Self { parsers }
+    }
+
+    /// Parse a file with the first parser for its MIME type that succeeds
+    pub async fn parse(&self, path: &Path) -> Result<String> {
+        let data = tokio::fs::read(path).await.context("Failed to read file")?;
+        let mime = detect_mime(path, &data);
+
+        let mut candidates = self
+            .parsers
+            .iter()
+            .filter(|p| p.mime_types().contains(&mime))
+            .peekable();
+
+        let mut last_error = None;
+        while let Some(parser) = candidates.next() {
+            match parser.parse(path, &data).await {
+                Ok(content) => return Ok(content),
+                Err(e) => {
+                    // Only the last parser's error is returned; earlier ones are warnings
+                    if candidates.peek().is_some() {
+                        eprintln!(
+                            "Warning: Failed to parse {} with {}: {}",
+                            path.display(),
+                            parser.name(),
+                            e
+                        );
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("no parser available for {}", mime)))
+    }
+}
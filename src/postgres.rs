@@ -0,0 +1,1024 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use clap::ValueEnum;
+use deadpool_postgres::{Client, GenericClient, Manager, ManagerConfig, Pool, RecyclingMethod};
+use postgres_native_tls::MakeTlsConnector;
+use std::collections::HashMap;
+use tokio_postgres::types::ToSql;
+
+use crate::cli::Cli;
+use crate::qdrant::{
+    CollectionInfo, CollectionMetadata, Distance, DocumentChunk, Filter, ScoredChunk, SearchParams,
+    StorageSettings,
+};
+use crate::store::{check_vector_size, VectorStore};
+
+/// Schema with one table per collection
+const SCHEMA: &str = "know";
+
+/// Key of the advisory lock held while migrating or creating collections, so
+/// concurrent runs don't apply the same migration or create the same
+/// collection twice
+const CATALOG_LOCK: i64 = 0x6b6e_6f77;
+
+/// Schema changes, applied in order on first use and recorded in
+/// know_meta.migrations. Only ever append to this list.
+const MIGRATIONS: &[&str] = &["
+    CREATE EXTENSION IF NOT EXISTS vector;
+    CREATE SCHEMA IF NOT EXISTS know;
+    CREATE TABLE know_meta.collections (
+        name text PRIMARY KEY,
+        vector_size integer NOT NULL,
+        distance text NOT NULL,
+        hnsw_m integer,
+        hnsw_ef_construct integer,
+        metadata jsonb
+    );
+"];
+
+/// Largest vectors pgvector can build an HNSW index for
+const MAX_INDEXED_DIMENSIONS: usize = 2000;
+
+/// Longest table name PostgreSQL keeps, in bytes
+const MAX_IDENTIFIER_LENGTH: usize = 63;
+
+/// pgvector's default and largest hnsw.ef_search
+const DEFAULT_EF_SEARCH: usize = 40;
+const MAX_EF_SEARCH: usize = 1000;
+
+type Params<'a> = Vec<&'a (dyn ToSql + Sync)>;
+
+/// Collections kept in PostgreSQL with pgvector: one table per collection
+/// with the payload as JSONB, and a catalog of their settings and metadata
+pub struct PostgresStore {
+    pool: Pool,
+    batch_size: usize,
+    /// Whether pgvector (0.8 or later) can keep scanning the HNSW index until
+    /// enough rows pass a filter
+    iterative_scan: bool,
+}
+
+/// A collection's row in know_meta.collections
+struct CatalogEntry {
+    vector_size: usize,
+    distance: Distance,
+    hnsw_m: Option<usize>,
+    hnsw_ef_construct: Option<usize>,
+}
+
+impl PostgresStore {
+    /// Connect to the database at --store-url and bring its schema up to date
+    pub async fn connect(cli: &Cli) -> Result<Self> {
+        let url = cli
+            .store_url
+            .as_deref()
+            .context("--store postgres needs --store-url postgres://...")?;
+        Self::open(url, cli.upsert_batch_size).await
+    }
+
+    /// Connect to the database at `url`, upserting at most `batch_size` points
+    /// per statement
+    async fn open(url: &str, batch_size: usize) -> Result<Self> {
+        let config: tokio_postgres::Config = url.parse().context("Invalid --store-url")?;
+        let tls = native_tls::TlsConnector::new().context("Failed to set up TLS")?;
+        // Verified recycling checks each connection before handing it out, so
+        // connections the server dropped are replaced with new ones
+        let manager = Manager::from_config(
+            config,
+            MakeTlsConnector::new(tls),
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Verified,
+            },
+        );
+        let pool = Pool::builder(manager)
+            .build()
+            .context("Failed to set up the PostgreSQL connection pool")?;
+
+        let mut client = pool
+            .get()
+            .await
+            .context("Failed to connect to PostgreSQL")?;
+        migrate(&mut client).await?;
+        let version: Option<String> = client
+            .query_opt(
+                "SELECT extversion FROM pg_extension WHERE extname = 'vector'",
+                &[],
+            )
+            .await
+            .context("Failed to get the pgvector version")?
+            .map(|row| row.get(0));
+        drop(client);
+
+        Ok(Self {
+            pool,
+            batch_size,
+            iterative_scan: version.is_some_and(|v| supports_iterative_scan(&v)),
+        })
+    }
+
+    /// A connection from the pool
+    async fn client(&self) -> Result<Client> {
+        self.pool
+            .get()
+            .await
+            .context("Failed to connect to PostgreSQL")
+    }
+}
+
+/// Create a collection's table and indexes and record it in the catalog, in a
+/// transaction holding the catalog lock
+async fn create(
+    tx: &impl GenericClient,
+    collection: &str,
+    vector_size: usize,
+    distance: Distance,
+    hnsw_m: Option<usize>,
+    hnsw_ef_construct: Option<usize>,
+) -> Result<()> {
+    let table = table(collection)?;
+    tx.batch_execute(&format!(
+        "CREATE TABLE {table} (
+            id text PRIMARY KEY,
+            embedding vector({vector_size}) NOT NULL,
+            payload jsonb NOT NULL
+        );
+        CREATE INDEX ON {table} USING gin (payload jsonb_path_ops);",
+        table = table,
+        vector_size = vector_size
+    ))
+    .await
+    .with_context(|| format!("Failed to create collection '{}'", collection))?;
+
+    if vector_size <= MAX_INDEXED_DIMENSIONS {
+        let mut options = Vec::new();
+        if let Some(m) = hnsw_m {
+            options.push(format!("m = {}", m));
+        }
+        if let Some(ef) = hnsw_ef_construct {
+            options.push(format!("ef_construction = {}", ef));
+        }
+        let with = if options.is_empty() {
+            String::new()
+        } else {
+            format!(" WITH ({})", options.join(", "))
+        };
+        tx.batch_execute(&format!(
+            "CREATE INDEX ON {} USING hnsw (embedding {}){}",
+            table,
+            operator_class(distance),
+            with
+        ))
+        .await
+        .with_context(|| format!("Failed to index collection '{}'", collection))?;
+    } else {
+        eprintln!(
+            "Warning: pgvector can't index vectors of more than {} dimensions, so searches \
+            of '{}' compare the query with every point.",
+            MAX_INDEXED_DIMENSIONS, collection
+        );
+    }
+
+    tx.execute(
+        "INSERT INTO know_meta.collections
+            (name, vector_size, distance, hnsw_m, hnsw_ef_construct)
+        VALUES ($1, $2, $3, $4, $5)",
+        &[
+            &collection,
+            &(vector_size as i32),
+            &distance.as_str(),
+            &hnsw_m.map(|m| m as i32),
+            &hnsw_ef_construct.map(|ef| ef as i32),
+        ],
+    )
+    .await
+    .context("Failed to record collection")?;
+
+    Ok(())
+}
+
+/// Hold the catalog lock until the end of the transaction
+async fn lock_catalog(tx: &impl GenericClient) -> Result<()> {
+    tx.execute("SELECT pg_advisory_xact_lock($1)", &[&CATALOG_LOCK])
+        .await
+        .context("Failed to lock the catalog")?;
+    Ok(())
+}
+
+/// Apply the migrations the database hasn't seen yet
+async fn migrate(client: &mut Client) -> Result<()> {
+    let tx = client.transaction().await?;
+    lock_catalog(&tx).await?;
+    tx.batch_execute(
+        "CREATE SCHEMA IF NOT EXISTS know_meta;
+        CREATE TABLE IF NOT EXISTS know_meta.migrations (
+            version integer PRIMARY KEY,
+            applied_at timestamptz NOT NULL DEFAULT now()
+        );",
+    )
+    .await
+    .context("Failed to create the migrations table")?;
+
+    let applied: i32 = tx
+        .query_one(
+            "SELECT coalesce(max(version), 0) FROM know_meta.migrations",
+            &[],
+        )
+        .await?
+        .get(0);
+    for (version, migration) in (1..).zip(MIGRATIONS).skip(applied as usize) {
+        tx.batch_execute(migration)
+            .await
+            .with_context(|| format!("Failed to apply schema migration {}", version))?;
+        tx.execute(
+            "INSERT INTO know_meta.migrations (version) VALUES ($1)",
+            &[&version],
+        )
+        .await?;
+    }
+
+    tx.commit().await.context("Failed to migrate the schema")
+}
+
+/// Settings and size of a collection, or None if it doesn't exist
+async fn catalog_entry(
+    client: &impl GenericClient,
+    collection: &str,
+) -> Result<Option<CatalogEntry>> {
+    let row = client
+        .query_opt(
+            "SELECT vector_size, distance, hnsw_m, hnsw_ef_construct
+            FROM know_meta.collections WHERE name = $1",
+            &[&collection],
+        )
+        .await
+        .context("Failed to get collection info")?;
+
+    Ok(row.map(|row| CatalogEntry {
+        vector_size: row.get::<_, i32>(0) as usize,
+        distance: Distance::from_str(row.get(1), true).unwrap_or_default(),
+        hnsw_m: row.get::<_, Option<i32>>(2).map(|m| m as usize),
+        hnsw_ef_construct: row.get::<_, Option<i32>>(3).map(|ef| ef as usize),
+    }))
+}
+
+/// Fail unless a collection exists
+async fn require(client: &impl GenericClient, collection: &str) -> Result<()> {
+    if catalog_entry(client, collection).await?.is_none() {
+        anyhow::bail!("Collection '{}' not found", collection);
+    }
+    Ok(())
+}
+
+/// Whether a pgvector version (e.g. 0.8.0) has hnsw.iterative_scan
+fn supports_iterative_scan(version: &str) -> bool {
+    let mut parts = version
+        .split('.')
+        .map(|part| part.parse::<u32>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+    (major, minor) >= (0, 8)
+}
+
+/// Schema-qualified, quoted table of a collection
+fn table(collection: &str) -> Result<String> {
+    Ok(format!("{}.{}", SCHEMA, quote(collection)?))
+}
+
+fn quote(collection: &str) -> Result<String> {
+    if collection.is_empty() || collection.len() > MAX_IDENTIFIER_LENGTH {
+        anyhow::bail!(
+            "Invalid collection name '{}': PostgreSQL table names have 1 to {} bytes",
+            collection,
+            MAX_IDENTIFIER_LENGTH
+        );
+    }
+    Ok(format!("\"{}\"", collection.replace('"', "\"\"")))
+}
+
+/// pgvector's text form of a vector, e.g. [1,2,3]
+fn vector_literal(vector: &[f32]) -> String {
+    serde_json::to_string(vector).unwrap_or_default()
+}
+
+fn operator(distance: Distance) -> &'static str {
+    match distance {
+        Distance::Cosine => "<=>",
+        Distance::Dot => "<#>",
+        Distance::Euclid => "<->",
+        Distance::Manhattan => "<+>",
+    }
+}
+
+fn operator_class(distance: Distance) -> &'static str {
+    match distance {
+        Distance::Cosine => "vector_cosine_ops",
+        Distance::Dot => "vector_ip_ops",
+        Distance::Euclid => "vector_l2_ops",
+        Distance::Manhattan => "vector_l1_ops",
+    }
+}
+
+/// Score of a hit as a similarity that grows with closeness: pgvector orders
+/// by a distance for every metric, so cosine and dot scores are turned back
+/// into Qdrant's and the other distances `d` into `1 / (1 + d)`
+fn score(distance: Distance, param: usize) -> String {
+    let distance_to_query = format!("embedding {} ${}::text::vector", operator(distance), param);
+    match distance {
+        Distance::Cosine => format!("1 - ({})", distance_to_query),
+        Distance::Dot => format!("-({})", distance_to_query),
+        Distance::Euclid | Distance::Manhattan => format!("1 / (1 + ({}))", distance_to_query),
+    }
+}
+
+/// SQL condition for a filter, as SQL/JSON path tests on the payload whose
+/// paths are bound from parameter `first_param` on
+fn filter_sql(filter: &Filter, first_param: usize) -> (String, Vec<String>) {
+    let mut clauses = Vec::new();
+    let mut paths = Vec::new();
+    let conditions = filter
+        .must
+        .iter()
+        .map(|c| (c, false))
+        .chain(filter.must_not.iter().map(|c| (c, true)));
+    for (condition, negated) in conditions {
+        let test = format!("payload @? ${}::text::jsonpath", first_param + paths.len());
+        clauses.push(if negated {
+            format!("NOT ({})", test)
+        } else {
+            test
+        });
+        paths.push(condition.json_path());
+    }
+
+    if clauses.is_empty() {
+        ("TRUE".to_string(), paths)
+    } else {
+        (clauses.join(" AND "), paths)
+    }
+}
+
+#[async_trait]
+impl VectorStore for PostgresStore {
+    async fn is_available(&self) -> bool {
+        match self.pool.get().await {
+            Ok(client) => client.simple_query("SELECT 1").await.is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    async fn ensure_collection(
+        &self,
+        collection: &str,
+        vector_size: usize,
+        settings: &StorageSettings,
+    ) -> Result<()> {
+        // Check and create under the lock, so concurrent ingests into a new
+        // collection don't both create it
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        lock_catalog(&tx).await?;
+        if let Some(entry) = catalog_entry(&tx, collection).await? {
+            return check_vector_size(collection, entry.vector_size, vector_size);
+        }
+
+        let supported = StorageSettings {
+            distance: settings.distance,
+            hnsw_m: settings.hnsw_m,
+            hnsw_ef_construct: settings.hnsw_ef_construct,
+            hnsw_ef: settings.hnsw_ef,
+            ..Default::default()
+        };
+        if *settings != supported {
+            eprintln!(
+                "Warning: The PostgreSQL store only supports the distance and HNSW settings; \
+                the other storage settings of '{}' are ignored.",
+                collection
+            );
+        }
+
+        create(
+            &tx,
+            collection,
+            vector_size,
+            settings.distance.unwrap_or_default(),
+            settings.hnsw_m,
+            settings.hnsw_ef_construct,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn create_collection_like(
+        &self,
+        collection: &str,
+        like: &CollectionInfo,
+        vector_size: usize,
+    ) -> Result<()> {
+        let distance = like
+            .distance
+            .as_deref()
+            .and_then(|d| Distance::from_str(d, true).ok())
+            .unwrap_or_default();
+
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        lock_catalog(&tx).await?;
+        if catalog_entry(&tx, collection).await?.is_some() {
+            anyhow::bail!("Collection '{}' already exists", collection);
+        }
+        create(
+            &tx,
+            collection,
+            vector_size,
+            distance,
+            like.hnsw_m,
+            like.hnsw_ef_construct,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Insert or replace points, at most --upsert-batch-size per statement
+    async fn upsert_batch(
+        &self,
+        collection: &str,
+        chunks: &[DocumentChunk],
+        embeddings: Vec<Vec<f32>>,
+    ) -> Result<()> {
+        let table = table(collection)?;
+        let rows: Vec<(&String, String, serde_json::Value)> = chunks
+            .iter()
+            .zip(&embeddings)
+            .map(|(chunk, embedding)| (&chunk.id, vector_literal(embedding), chunk.payload()))
+            .collect();
+
+        let client = self.client().await?;
+        for batch in rows.chunks(self.batch_size.max(1)) {
+            let mut values = Vec::with_capacity(batch.len());
+            let mut params: Params = Vec::with_capacity(batch.len() * 3);
+            for (id, vector, payload) in batch {
+                let n = params.len();
+                values.push(format!(
+                    "(${}, ${}::text::vector, ${})",
+                    n + 1,
+                    n + 2,
+                    n + 3
+                ));
+                params.extend([id as &(dyn ToSql + Sync), vector, payload]);
+            }
+
+            client
+                .execute(
+                    &format!(
+                        "INSERT INTO {} (id, embedding, payload) VALUES {}
+                        ON CONFLICT (id) DO UPDATE
+                        SET embedding = EXCLUDED.embedding, payload = EXCLUDED.payload",
+                        table,
+                        values.join(", ")
+                    ),
+                    &params,
+                )
+                .await
+                .context("Failed to upsert points")?;
+        }
+
+        Ok(())
+    }
+
+    /// Nearest points by the collection's distance; `hnsw_ef` sets pgvector's
+    /// hnsw.ef_search, the quantization parameters don't apply.
+    ///
+    /// An HNSW scan returns at most hnsw.ef_search rows, and filters only
+    /// apply to those, so ef_search is raised to the limit and filtered
+    /// searches scan iteratively until enough rows match. Where that isn't
+    /// possible (limits past pgvector's largest ef_search, or filters before
+    /// pgvector 0.8) the search compares the query with every matching row.
+    async fn search_scored(
+        &self,
+        collection: &str,
+        query_embedding: Vec<f32>,
+        limit: usize,
+        filter: &Filter,
+        with_vector: bool,
+        params: &SearchParams,
+    ) -> Result<Vec<ScoredChunk>> {
+        let table = table(collection)?;
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        let entry = catalog_entry(&tx, collection)
+            .await?
+            .with_context(|| format!("Collection '{}' not found", collection))?;
+
+        let filtered = !filter.must.is_empty() || !filter.must_not.is_empty();
+        let ef_search = params.hnsw_ef.unwrap_or(DEFAULT_EF_SEARCH).max(limit);
+        let settings = if ef_search > MAX_EF_SEARCH || (filtered && !self.iterative_scan) {
+            "SET LOCAL enable_indexscan = off".to_string()
+        } else if filtered {
+            format!(
+                "SET LOCAL hnsw.ef_search = {}; SET LOCAL hnsw.iterative_scan = strict_order",
+                ef_search
+            )
+        } else {
+            format!("SET LOCAL hnsw.ef_search = {}", ef_search)
+        };
+        tx.batch_execute(&settings)
+            .await
+            .context("Failed to configure the search")?;
+
+        let (condition, paths) = filter_sql(filter, 3);
+        let query = vector_literal(&query_embedding);
+        let limit = limit as i64;
+        let mut sql_params: Params = vec![&query, &limit];
+        sql_params.extend(paths.iter().map(|p| p as &(dyn ToSql + Sync)));
+
+        let rows = tx
+            .query(
+                &format!(
+                    "SELECT id, payload, ({})::real, {} FROM {} WHERE {}
+                    ORDER BY embedding {} $1::text::vector LIMIT $2",
+                    score(entry.distance, 1),
+                    if with_vector {
+                        "embedding::text"
+                    } else {
+                        "NULL::text"
+                    },
+                    table,
+                    condition,
+                    operator(entry.distance)
+                ),
+                &sql_params,
+            )
+            .await
+            .context("Failed to search")?;
+        tx.commit().await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let vector = row
+                    .get::<_, Option<String>>(3)
+                    .map(|text| serde_json::from_str(&text))
+                    .transpose()
+                    .context("Failed to parse point vector")?;
+                Ok(ScoredChunk {
+                    chunk: DocumentChunk::from_payload(row.get(0), row.get(1))?,
+                    score: row.get(2),
+                    vector,
+                    collection: None,
+                    fused_score: None,
+                })
+            })
+            .collect()
+    }
+
+    async fn scroll(&self, collection: &str, filter: &Filter) -> Result<Vec<DocumentChunk>> {
+        let (condition, paths) = filter_sql(filter, 1);
+        let params: Params = paths.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+        let rows = self
+            .client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT id, payload FROM {} WHERE {} ORDER BY id",
+                    table(collection)?,
+                    condition
+                ),
+                &params,
+            )
+            .await
+            .context("Failed to scroll points")?;
+
+        rows.into_iter()
+            .map(|row| DocumentChunk::from_payload(row.get(0), row.get(1)))
+            .collect()
+    }
+
+    async fn scroll_fields(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        fields: &[&str],
+    ) -> Result<Vec<serde_json::Value>> {
+        let (condition, paths) = filter_sql(filter, 2);
+        let mut params: Params = vec![&fields];
+        params.extend(paths.iter().map(|p| p as &(dyn ToSql + Sync)));
+        let rows = self
+            .client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT coalesce(
+                        (SELECT jsonb_object_agg(key, value) FROM jsonb_each(payload)
+                        WHERE key = ANY($1)),
+                        '{{}}'::jsonb
+                    ) FROM {} WHERE {} ORDER BY id",
+                    table(collection)?,
+                    condition
+                ),
+                &params,
+            )
+            .await
+            .context("Failed to scroll points")?;
+
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn vectors(&self, collection: &str, ids: &[String]) -> Result<HashMap<String, Vec<f32>>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT id, embedding::text FROM {} WHERE id = ANY($1)",
+                    table(collection)?
+                ),
+                &[&ids],
+            )
+            .await
+            .context("Failed to retrieve points")?;
+
+        rows.into_iter()
+            .map(|row| {
+                let vector =
+                    serde_json::from_str(row.get(1)).context("Failed to parse point vector")?;
+                Ok((row.get(0), vector))
+            })
+            .collect()
+    }
+
+    async fn set_sources(
+        &self,
+        collection: &str,
+        id: &str,
+        source: &str,
+        other_sources: &[String],
+    ) -> Result<()> {
+        let payload = serde_json::json!({ "source": source, "other_sources": other_sources });
+        let updated = self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "UPDATE {} SET payload = payload || $1 WHERE id = $2",
+                    table(collection)?
+                ),
+                &[&payload, &id],
+            )
+            .await
+            .context("Failed to set point payload")?;
+        if updated == 0 {
+            anyhow::bail!("Point '{}' not found in collection '{}'", id, collection);
+        }
+        Ok(())
+    }
+
+    async fn delete_points(&self, collection: &str, filter: &Filter) -> Result<()> {
+        let (condition, paths) = filter_sql(filter, 1);
+        let params: Params = paths.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+        self.client()
+            .await?
+            .execute(
+                &format!("DELETE FROM {} WHERE {}", table(collection)?, condition),
+                &params,
+            )
+            .await
+            .context("Failed to delete points")?;
+        Ok(())
+    }
+
+    async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>> {
+        let client = self.client().await?;
+        let Some(entry) = catalog_entry(&client, collection).await? else {
+            return Ok(None);
+        };
+        let points: i64 = client
+            .query_one(&format!("SELECT count(*) FROM {}", table(collection)?), &[])
+            .await
+            .context("Failed to count points")?
+            .get(0);
+
+        let distance = entry.distance.as_str();
+        Ok(Some(CollectionInfo {
+            points_count: points as usize,
+            indexed_vectors_count: points as usize,
+            vector_size: Some(entry.vector_size),
+            distance: Some(distance.to_string()),
+            hnsw_m: entry.hnsw_m,
+            hnsw_ef_construct: entry.hnsw_ef_construct,
+            quantization: None,
+            on_disk: true,
+            on_disk_payload: true,
+            shards: None,
+            config: serde_json::json!({
+                "params": {
+                    "vectors": { "size": entry.vector_size, "distance": distance },
+                },
+                "hnsw_config": { "m": entry.hnsw_m, "ef_construct": entry.hnsw_ef_construct },
+            }),
+        }))
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        let rows = self
+            .client()
+            .await?
+            .query("SELECT name FROM know_meta.collections ORDER BY name", &[])
+            .await
+            .context("Failed to list collections")?;
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn copy_collection(&self, from: &str, to: &str) -> Result<usize> {
+        let (source, target) = (table(from)?, table(to)?);
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        lock_catalog(&tx).await?;
+        require(&tx, from).await?;
+        if catalog_entry(&tx, to).await?.is_some() {
+            anyhow::bail!("Collection '{}' already exists", to);
+        }
+
+        tx.batch_execute(&format!(
+            "CREATE TABLE {} (LIKE {} INCLUDING ALL)",
+            target, source
+        ))
+        .await
+        .with_context(|| format!("Failed to create collection '{}'", to))?;
+        let copied = tx
+            .execute(
+                &format!("INSERT INTO {} SELECT * FROM {}", target, source),
+                &[],
+            )
+            .await
+            .context("Failed to copy points")?;
+        tx.execute(
+            "INSERT INTO know_meta.collections
+            SELECT $2, vector_size, distance, hnsw_m, hnsw_ef_construct, metadata
+            FROM know_meta.collections WHERE name = $1",
+            &[&from, &to],
+        )
+        .await
+        .context("Failed to record collection")?;
+
+        tx.commit().await?;
+        Ok(copied as usize)
+    }
+
+    /// Rename the collection's table in place
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<usize> {
+        let source = table(from)?;
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        lock_catalog(&tx).await?;
+        require(&tx, from).await?;
+        if catalog_entry(&tx, to).await?.is_some() {
+            anyhow::bail!("Collection '{}' already exists", to);
+        }
+
+        tx.batch_execute(&format!("ALTER TABLE {} RENAME TO {}", source, quote(to)?))
+            .await
+            .with_context(|| format!("Failed to rename collection '{}'", from))?;
+        tx.execute(
+            "UPDATE know_meta.collections SET name = $2 WHERE name = $1",
+            &[&from, &to],
+        )
+        .await
+        .context("Failed to record collection")?;
+        let renamed: i64 = tx
+            .query_one(&format!("SELECT count(*) FROM {}", table(to)?), &[])
+            .await?
+            .get(0);
+
+        tx.commit().await?;
+        Ok(renamed as usize)
+    }
+
+    async fn delete_collection(&self, collection: &str) -> Result<()> {
+        let table = table(collection)?;
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        tx.batch_execute(&format!("DROP TABLE IF EXISTS {}", table))
+            .await
+            .context("Failed to delete collection")?;
+        tx.execute(
+            "DELETE FROM know_meta.collections WHERE name = $1",
+            &[&collection],
+        )
+        .await
+        .context("Failed to delete collection")?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_metadata(&self, collection: &str) -> Result<Option<CollectionMetadata>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT metadata FROM know_meta.collections WHERE name = $1",
+                &[&collection],
+            )
+            .await
+            .context("Failed to get collection metadata")?;
+
+        match row.and_then(|row| row.get::<_, Option<serde_json::Value>>(0)) {
+            Some(metadata) => Ok(Some(
+                serde_json::from_value(metadata).context("Failed to parse collection metadata")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn set_metadata(&self, collection: &str, metadata: &CollectionMetadata) -> Result<()> {
+        let metadata = serde_json::to_value(metadata)?;
+        let updated = self
+            .client()
+            .await?
+            .execute(
+                "UPDATE know_meta.collections SET metadata = $2 WHERE name = $1",
+                &[&collection, &metadata],
+            )
+            .await
+            .context("Failed to store collection metadata")?;
+        if updated == 0 {
+            anyhow::bail!("Collection '{}' not found", collection);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qdrant::Condition;
+    use std::time::{Duration, Instant};
+
+    /// Database to run the tests against, e.g. the pgvector service in
+    /// docker-compose.yml; without it the tests are skipped
+    const URL_VAR: &str = "KNOW_TEST_POSTGRES_URL";
+
+    async fn store() -> Option<PostgresStore> {
+        let Ok(url) = std::env::var(URL_VAR) else {
+            eprintln!("Skipping: set {} to test the PostgreSQL store", URL_VAR);
+            return None;
+        };
+        Some(PostgresStore::open(&url, 100).await.unwrap())
+    }
+
+    /// A collection name no other test run uses
+    fn collection(name: &str) -> String {
+        format!("test-{}-{}", name, uuid::Uuid::new_v4().simple())
+    }
+
+    fn chunk(id: &str, source: &str) -> DocumentChunk {
+        DocumentChunk::from_payload(
+            id.to_string(),
+            serde_json::json!({ "content": format!("text of {}", id), "source": source }),
+        )
+        .unwrap()
+    }
+
+    /// Nearest points to [1, 0]
+    async fn search(
+        store: &PostgresStore,
+        collection: &str,
+        limit: usize,
+        filter: &Filter,
+    ) -> Vec<ScoredChunk> {
+        let query = vec![1.0, 0.0];
+        store
+            .search_scored(
+                collection,
+                query,
+                limit,
+                filter,
+                false,
+                &SearchParams::default(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn concurrent_ensures_create_a_collection_once() {
+        let (Some(first), Some(second)) = (store().await, store().await) else {
+            return;
+        };
+        let name = collection("ensure");
+        let settings = StorageSettings::default();
+
+        let (a, b) = tokio::join!(
+            first.ensure_collection(&name, 3, &settings),
+            second.ensure_collection(&name, 3, &settings)
+        );
+        a.unwrap();
+        b.unwrap();
+        assert!(first.ensure_collection(&name, 4, &settings).await.is_err());
+
+        first.delete_collection(&name).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn pool_runs_requests_concurrently() {
+        let Some(store) = store().await else {
+            return;
+        };
+        let sleep = || async {
+            store
+                .client()
+                .await
+                .unwrap()
+                .simple_query("SELECT pg_sleep(0.5)")
+                .await
+                .unwrap();
+        };
+
+        let started = Instant::now();
+        tokio::join!(sleep(), sleep(), sleep(), sleep());
+        assert!(started.elapsed() < Duration::from_millis(1500));
+    }
+
+    #[tokio::test]
+    async fn pool_replaces_dropped_connections() {
+        let Some(store) = store().await else {
+            return;
+        };
+        let dropped = store.client().await.unwrap();
+        let pid: i32 = dropped
+            .query_one("SELECT pg_backend_pid()", &[])
+            .await
+            .unwrap()
+            .get(0);
+        let other = store.client().await.unwrap();
+        drop(dropped);
+
+        other
+            .execute("SELECT pg_terminate_backend($1)", &[&pid])
+            .await
+            .unwrap();
+        while other
+            .query_opt("SELECT 1 FROM pg_stat_activity WHERE pid = $1", &[&pid])
+            .await
+            .unwrap()
+            .is_some()
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        drop(other);
+
+        for _ in 0..3 {
+            store.list_collections().await.unwrap();
+        }
+        assert!(store.is_available().await);
+    }
+
+    #[tokio::test]
+    async fn searches_return_every_result_asked_for() {
+        let Some(mut store) = store().await else {
+            return;
+        };
+        let name = collection("search");
+        let settings = StorageSettings {
+            distance: Some(Distance::Cosine),
+            ..Default::default()
+        };
+        store.ensure_collection(&name, 2, &settings).await.unwrap();
+
+        // Many points close to the query, and a few rare ones far from it that
+        // an HNSW scan of the nearest hnsw.ef_search points never reaches
+        let mut chunks = Vec::new();
+        let mut vectors = Vec::new();
+        for i in 0..1100 {
+            chunks.push(chunk(&format!("common-{}", i), "common.md"));
+            vectors.push(vec![1.0, i as f32 / 1100.0]);
+        }
+        for i in 0..10 {
+            chunks.push(chunk(&format!("rare-{}", i), "rare.md"));
+            vectors.push(vec![-1.0, i as f32 / 10.0]);
+        }
+        store.upsert_batch(&name, &chunks, vectors).await.unwrap();
+
+        let rare = Filter::default().with(Condition::matches("source", "rare.md"));
+
+        let hits = search(&store, &name, 100, &Filter::default()).await;
+        assert_eq!(hits.len(), 100);
+        assert!(hits.windows(2).all(|w| w[0].score >= w[1].score));
+
+        let hits = search(&store, &name, 1110, &Filter::default()).await;
+        assert_eq!(hits.len(), 1110);
+
+        let hits = search(&store, &name, 10, &rare).await;
+        assert_eq!(hits.len(), 10);
+        assert!(hits.iter().all(|hit| hit.chunk.source == "rare.md"));
+
+        // Without iterative scans, filtered searches compare every matching row
+        store.iterative_scan = false;
+        let hits = search(&store, &name, 10, &rare).await;
+        assert_eq!(hits.len(), 10);
+
+        store.delete_collection(&name).await.unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::config::Config;
+use crate::time::today;
+
+/// Built-in system prompt, used unless a template is configured
+pub const DEFAULT_TEMPLATE: &str = "You are a helpful assistant. Answer the user's question using only the context provided below. \
+    If the context doesn't contain relevant information, say so. \
+    When the context is split into numbered blocks, cite the blocks supporting each statement \
+    with their bracketed numbers, like [1] or [2][3], and never cite a number that isn't in the context.\n\n\
+    Context:\n{{context}}";
+
+/// Values substituted for `{{name}}` placeholders in a template
+#[derive(Default)]
+pub struct PromptVars<'a> {
+    pub context: &'a str,
+    pub question: &'a str,
+    pub collection: &'a str,
+    /// Earlier turns of the conversation, one `role: content` line each
+    pub history: &'a str,
+}
+
+/// A system prompt template with `{{context}}`, `{{question}}`, `{{date}}`,
+/// `{{collection}}` and `{{history}}` placeholders
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    text: String,
+}
+
+impl Default for PromptTemplate {
+    fn default() -> Self {
+        Self::new(DEFAULT_TEMPLATE)
+    }
+}
+
+impl PromptTemplate {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into() }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Fill in the placeholders. Templates that never mention the context get
+    /// it appended, so answers stay grounded.
+    pub fn render(&self, vars: &PromptVars) -> String {
+        let mut rendered = self
+            .text
+            .replace("{{question}}", vars.question)
+            .replace("{{date}}", &today())
+            .replace("{{collection}}", vars.collection)
+            .replace("{{history}}", vars.history);
+
+        if rendered.contains("{{context}}") {
+            rendered = rendered.replace("{{context}}", vars.context);
+        } else if !vars.context.is_empty() {
+            rendered.push_str("\n\nContext:\n");
+            rendered.push_str(vars.context);
+        }
+        rendered
+    }
+
+    /// Load a template given as a file path, a name from the config's
+    /// `[prompts]` table, or a name of a file in the config's `prompts` directory
+    pub fn load(name: &str, config: &Config) -> Result<Self> {
+        let path = Path::new(name);
+        if path.is_file() {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read prompt template {}", path.display()))?;
+            return Ok(Self::new(text));
+        }
+
+        if let Some(text) = config.prompts.get(name) {
+            return Ok(Self::new(text.clone()));
+        }
+
+        if let Some(dir) = &config.dir {
+            let path = dir.join("prompts").join(format!("{}.txt", name));
+            if path.is_file() {
+                let text = std::fs::read_to_string(&path).with_context(|| {
+                    format!("Failed to read prompt template {}", path.display())
+                })?;
+                return Ok(Self::new(text));
+            }
+        }
+
+        anyhow::bail!(
+            "Prompt template '{}' is not a file or a prompt defined in the config",
+            name
+        )
+    }
+
+    /// Pick the template for querying a collection: `--prompt`, then the
+    /// collection's entry in the config, then the template stored with the
+    /// collection, then the built-in default
+    pub fn resolve(
+        prompt: Option<&str>,
+        config: &Config,
+        collection: &str,
+        stored: Option<&str>,
+    ) -> Result<Self> {
+        if let Some(name) = prompt {
+            return Self::load(name, config);
+        }
+        if let Some(name) = config
+            .collection(collection)
+            .and_then(|c| c.prompt.as_deref())
+        {
+            return Self::load(name, config);
+        }
+        Ok(stored.map(Self::new).unwrap_or_default())
+    }
+}
@@ -1,31 +1,304 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::{Context, Result};
+use async_trait::async_trait;
+use clap::{Args, ValueEnum};
+use futures::{StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
 
-const DEFAULT_QDRANT_URL: &str = "http://localhost:6333";
+use crate::cli::Cli;
+use crate::store::{check_vector_size, VectorStore};
+
+#[cfg(feature = "grpc")]
+mod grpc;
+
+/// Wait before the first retry of a failed request; doubled on every further retry
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Time allowed for establishing a connection, separate from the request timeout
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const SCROLL_PAGE_SIZE: usize = 256;
+
+/// Collection holding every collection's metadata record, one point per
+/// collection, so the records don't count as points of the collections
+const METADATA_COLLECTION: &str = "know_meta";
+
+/// Payload fields indexed on new collections, with their Qdrant schema types
+const PAYLOAD_INDEXES: &[(&str, &str)] = &[
+    ("source", "keyword"),
+    ("kind", "keyword"),
+    ("doc_id", "keyword"),
+    ("position", "integer"),
+    ("page", "integer"),
+];
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DocumentChunk {
     pub id: String,
     pub content: String,
     pub source: String,
+    #[serde(default)]
+    pub kind: PointKind,
+    /// For question points, the ID of the chunk the question was generated from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// For question points, the generated question their vector embeds. Kept on
+    /// a collapsed hit to show which question matched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub question: Option<String>,
+    /// ID shared by every point ingested from the same document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc_id: Option<String>,
+    /// Position of the chunk within its document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+    /// Nearest markdown heading at or before the start of the chunk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    /// Page the chunk starts on, for documents with page breaks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    /// Other sources containing a near-duplicate of this chunk
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub other_sources: Vec<String>,
+    /// When the document was ingested, as an RFC 3339 timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingested_at: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
-struct CreateCollectionRequest {
-    vectors: VectorConfig,
+/// What a point in the collection represents
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PointKind {
+    /// A chunk of a document's text
+    #[default]
+    Chunk,
+    /// A generated summary of a whole document
+    Summary,
+    /// A generated question answered by a chunk, carrying that chunk's payload
+    Question,
 }
 
-#[derive(Serialize, Debug)]
-struct VectorConfig {
-    size: usize,
-    distance: String,
+/// Settings stored with a collection, so they travel with it on push and pull
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CollectionMetadata {
+    /// Prompt template to answer queries against the collection with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// Embedding model the collection's vectors were made with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embed_model: Option<String>,
+    /// Backend the embeddings were made with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Dimensions of the embeddings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<usize>,
+    /// How documents were split into chunks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunker: Option<String>,
+    /// Maximum chunk length in characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<usize>,
+    /// Version of know that last ingested into the collection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// When documents were last ingested, as an RFC 3339 timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_ingest: Option<String>,
+    /// Parameters to search the collection with
+    #[serde(default, skip_serializing_if = "SearchParams::is_empty")]
+    pub search: SearchParams,
+    /// Fields written by other versions of know, preserved on update
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl CollectionMetadata {
+    /// Fail with a way out if the collection was embedded with a model other
+    /// than `embed_model`. Collections that don't record their model pass.
+    pub fn check_embed_model(&self, collection: &str, embed_model: &str) -> Result<()> {
+        match &self.embed_model {
+            Some(stored) if stored != embed_model => anyhow::bail!(
+                "Collection '{}' was embedded with '{}', but '{}' is selected. Use \
+                --embed-model {} (or set embed_model for the collection in the config), or \
+                migrate it with 'know --collection {} --embed-model {} reembed'.",
+                collection,
+                stored,
+                embed_model,
+                stored,
+                collection,
+                embed_model
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl PointKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PointKind::Chunk => "chunk",
+            PointKind::Summary => "summary",
+            PointKind::Question => "question",
+        }
+    }
+}
+
+/// A single payload condition on a key: an exact value or a numeric range
+#[derive(Serialize, Debug, Clone)]
+pub struct Condition {
+    key: String,
+    #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+    matches: Option<MatchValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<RangeValue>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct MatchValue {
+    value: serde_json::Value,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct RangeValue {
+    gte: f64,
+    lte: f64,
+}
+
+impl Condition {
+    pub fn matches(key: &str, value: impl Into<serde_json::Value>) -> Self {
+        Self {
+            key: key.to_string(),
+            matches: Some(MatchValue {
+                value: value.into(),
+            }),
+            range: None,
+        }
+    }
+
+    /// Match numeric values between `gte` and `lte` inclusive
+    pub fn range(key: &str, gte: f64, lte: f64) -> Self {
+        Self {
+            key: key.to_string(),
+            matches: None,
+            range: Some(RangeValue { gte, lte }),
+        }
+    }
+
+    /// Whether a payload satisfies the condition, the way Qdrant evaluates it:
+    /// an array matches if any of its elements does
+    fn is_met_by(&self, payload: &serde_json::Value) -> bool {
+        let values = match payload.get(&self.key) {
+            Some(serde_json::Value::Array(values)) => values.iter().collect(),
+            Some(value) => vec![value],
+            None => return false,
+        };
+        values.into_iter().any(|value| {
+            let matched = self.matches.as_ref().is_none_or(|m| *value == m.value);
+            let in_range = self.range.as_ref().is_none_or(|range| {
+                value
+                    .as_f64()
+                    .is_some_and(|v| v >= range.gte && v <= range.lte)
+            });
+            matched && in_range
+        })
+    }
+
+    /// SQL/JSON path selecting the payload values that satisfy the condition.
+    /// Lax mode unwraps arrays, so like in Qdrant an array matches if any of
+    /// its elements does.
+    #[cfg(feature = "postgres")]
+    pub fn json_path(&self) -> String {
+        let key = serde_json::Value::from(self.key.as_str());
+        match (&self.matches, &self.range) {
+            (Some(m), _) => format!("$.{} ? (@ == {})", key, m.value),
+            (None, Some(range)) => {
+                format!("$.{} ? (@ >= {} && @ <= {})", key, range.gte, range.lte)
+            }
+            (None, None) => format!("$.{}", key),
+        }
+    }
+}
+
+/// Payload filter for searches
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Filter {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub must: Vec<Condition>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub must_not: Vec<Condition>,
+}
+
+impl Filter {
+    /// Only document chunks (points written before kinds existed count as chunks)
+    pub fn chunks() -> Self {
+        Self {
+            must: Vec::new(),
+            must_not: vec![
+                Condition::matches("kind", PointKind::Summary.as_str()),
+                Condition::matches("kind", PointKind::Question.as_str()),
+            ],
+        }
+    }
+
+    /// Document chunks plus the generated questions pointing at them
+    pub fn chunks_and_questions() -> Self {
+        Self {
+            must: Vec::new(),
+            must_not: vec![Condition::matches("kind", PointKind::Summary.as_str())],
+        }
+    }
+
+    /// Only points of the given kind
+    pub fn kind(kind: PointKind) -> Self {
+        match kind {
+            PointKind::Chunk => Self::chunks(),
+            PointKind::Summary | PointKind::Question => Self {
+                must: vec![Condition::matches("kind", kind.as_str())],
+                must_not: Vec::new(),
+            },
+        }
+    }
+
+    /// Add a condition that must match
+    pub fn with(mut self, condition: Condition) -> Self {
+        self.must.push(condition);
+        self
+    }
+
+    /// Whether a point's payload passes the filter, for stores that filter
+    /// points themselves
+    pub fn is_met_by(&self, payload: &serde_json::Value) -> bool {
+        self.must.iter().all(|c| c.is_met_by(payload))
+            && !self.must_not.iter().any(|c| c.is_met_by(payload))
+    }
+}
+
+/// A search hit together with its similarity score
+#[derive(Serialize, Debug, Clone)]
+pub struct ScoredChunk {
+    #[serde(flatten)]
+    pub chunk: DocumentChunk,
+    pub score: f32,
+    /// The point's vector, when requested from the search
+    #[serde(skip)]
+    pub vector: Option<Vec<f32>>,
+    /// Collection the hit came from, when several were searched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    /// Reciprocal rank fusion score the hit was ranked by when several
+    /// collections were searched; `score` stays the raw similarity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fused_score: Option<f32>,
 }
 
 #[derive(Serialize, Debug)]
-struct UpsertPointsRequest {
-    points: Vec<Point>,
+struct UpsertPointsRequest<'a> {
+    points: &'a [Point],
 }
 
 #[derive(Serialize, Debug)]
@@ -39,13 +312,117 @@ struct Point {
 struct PointPayload {
     content: String,
     source: String,
+    #[serde(default)]
+    kind: PointKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    question: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    doc_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    position: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    heading: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    page: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    other_sources: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ingested_at: Option<String>,
+}
+
+impl PointPayload {
+    fn from_chunk(chunk: &DocumentChunk) -> Self {
+        Self {
+            content: chunk.content.clone(),
+            source: chunk.source.clone(),
+            kind: chunk.kind,
+            parent_id: chunk.parent_id.clone(),
+            question: chunk.question.clone(),
+            doc_id: chunk.doc_id.clone(),
+            position: chunk.position,
+            heading: chunk.heading.clone(),
+            page: chunk.page,
+            other_sources: chunk.other_sources.clone(),
+            ingested_at: chunk.ingested_at.clone(),
+        }
+    }
+
+    fn into_chunk(self, id: String) -> DocumentChunk {
+        DocumentChunk {
+            id,
+            content: self.content,
+            source: self.source,
+            kind: self.kind,
+            parent_id: self.parent_id,
+            question: self.question,
+            doc_id: self.doc_id,
+            position: self.position,
+            heading: self.heading,
+            page: self.page,
+            other_sources: self.other_sources,
+            ingested_at: self.ingested_at,
+        }
+    }
+}
+
+impl DocumentChunk {
+    /// Everything but the ID, as stored in the chunk's point
+    pub fn payload(&self) -> serde_json::Value {
+        serde_json::to_value(PointPayload::from_chunk(self)).unwrap_or_default()
+    }
+
+    /// Rebuild a chunk from the ID and payload of its point
+    pub fn from_payload(id: String, payload: serde_json::Value) -> Result<Self> {
+        let payload: PointPayload =
+            serde_json::from_value(payload).context("Failed to parse point payload")?;
+        Ok(payload.into_chunk(id))
+    }
 }
 
 #[derive(Serialize, Debug)]
-struct SearchRequest {
+struct ScrollRequest<'a> {
+    filter: &'a Filter,
+    limit: usize,
+    with_payload: bool,
+    with_vector: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScrollResponse {
+    result: ScrollResult,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScrollResult {
+    points: Vec<ScrollPoint>,
+    next_page_offset: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScrollPoint {
+    id: serde_json::Value,
+    payload: Option<PointPayload>,
+}
+
+#[derive(Serialize, Debug)]
+struct SetPayloadRequest {
+    payload: serde_json::Value,
+    points: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct SearchRequest<'a> {
     vector: Vec<f32>,
     limit: usize,
     with_payload: bool,
+    with_vector: bool,
+    filter: &'a Filter,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -55,57 +432,482 @@ struct SearchResponse {
 
 #[derive(Deserialize, Debug)]
 struct SearchResult {
-    #[allow(dead_code)]
     id: serde_json::Value,
-    #[allow(dead_code)]
     score: f32,
     payload: Option<PointPayload>,
+    #[serde(default)]
+    vector: Option<Vec<f32>>,
+}
+
+/// ID of a collection's record in the metadata collection, derived from its name
+fn metadata_point_id(collection: &str) -> String {
+    uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, collection.as_bytes()).to_string()
+}
+
+/// Render a Qdrant point ID (UUID string or integer) as a string
+fn point_id_to_string(id: serde_json::Value) -> String {
+    match id {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// Turn an error response into an error carrying Qdrant's message
+async fn check_status(response: reqwest::Response, action: &str) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let message = error_message(response).await;
+    anyhow::bail!("{}: Qdrant returned {}: {}", action, status, message)
+}
+
+/// Qdrant's explanation of a failed request, given as {"status": {"error": "..."}}
+async fn error_message(response: reqwest::Response) -> String {
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|v| v.pointer("/status/error")?.as_str().map(str::to_string))
+        .unwrap_or(body);
+    message.trim().to_string()
+}
+
+/// A request to Qdrant that is retried on connection errors and 5xx responses
+struct Request {
+    builder: reqwest::RequestBuilder,
+    retries: u32,
+}
+
+impl Request {
+    fn json(mut self, body: &impl Serialize) -> Self {
+        self.builder = self.builder.json(body);
+        self
+    }
+
+    /// Send the request, retrying transient failures with exponential backoff,
+    /// and return the response whatever its status
+    async fn send_raw(self, action: &str) -> Result<reqwest::Response> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let request = self
+                .builder
+                .try_clone()
+                .context("Request body can't be retried")?;
+            let transient = match request.send().await {
+                Ok(response) if response.status().is_server_error() => {
+                    let status = response.status();
+                    format!(
+                        "Qdrant returned {}: {}",
+                        status,
+                        error_message(response).await
+                    )
+                }
+                Err(e) if e.is_connect() || e.is_timeout() || e.is_request() => e.to_string(),
+                result => return result.context(action.to_string()),
+            };
+
+            if attempt >= self.retries {
+                anyhow::bail!("{}: {} (after {} attempts)", action, transient, attempt + 1);
+            }
+            attempt += 1;
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Send the request and fail on error responses, with Qdrant's explanation
+    async fn send(self, action: &str) -> Result<reqwest::Response> {
+        let response = self.send_raw(action).await?;
+        check_status(response, action).await
+    }
+}
+
+/// Similarity metric of a collection's vectors
+#[derive(Clone, Copy, ValueEnum, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Distance {
+    #[default]
+    Cosine,
+    Dot,
+    Euclid,
+    Manhattan,
+}
+
+impl Distance {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Distance::Cosine => "Cosine",
+            Distance::Dot => "Dot",
+            Distance::Euclid => "Euclid",
+            Distance::Manhattan => "Manhattan",
+        }
+    }
+
+    /// Turn the score a search reports for this metric into a similarity that
+    /// grows with closeness, as the rest of retrieval expects. Euclid and
+    /// Manhattan report distances, which become `1 / (1 + d)`.
+    pub fn similarity(&self, score: f32) -> f32 {
+        match self {
+            Distance::Cosine | Distance::Dot => score,
+            Distance::Euclid | Distance::Manhattan => 1.0 / (1.0 + score),
+        }
+    }
+}
+
+/// Compression of a collection's vectors, trading some accuracy for memory
+#[derive(Clone, Copy, ValueEnum, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantization {
+    /// One byte per dimension, 4x smaller
+    Scalar,
+    /// One bit per dimension, 32x smaller; suits models with 1024+ dimensions
+    Binary,
+    /// Groups of dimensions replaced by codebook entries (see --compression)
+    Product,
+}
+
+/// Compression ratios Qdrant supports for product quantization
+const PRODUCT_COMPRESSIONS: [u32; 5] = [4, 8, 16, 32, 64];
+
+/// How a collection's vectors are indexed, stored and searched.
+///
+/// Everything but the search parameters is fixed when the collection is
+/// created; unset values keep Qdrant's defaults.
+#[derive(Args, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSettings {
+    /// Similarity metric [default: cosine]
+    #[arg(long, value_enum)]
+    pub distance: Option<Distance>,
+
+    /// Links per node of the HNSW graph; more improves recall but uses more memory
+    #[arg(long)]
+    pub hnsw_m: Option<usize>,
+
+    /// Neighbours considered while building the HNSW graph; more improves recall
+    /// but slows indexing
+    #[arg(long)]
+    pub hnsw_ef_construct: Option<usize>,
+
+    /// Neighbours considered while searching; more improves recall but slows queries
+    #[arg(long)]
+    pub hnsw_ef: Option<usize>,
+
+    /// Compress vectors to save memory; compressed vectors are always kept in RAM
+    #[arg(long, value_enum)]
+    pub quantization: Option<Quantization>,
+
+    /// Compression ratio for product quantization: 4, 8, 16, 32 or 64 [default: 16]
+    #[arg(long)]
+    pub compression: Option<u32>,
+
+    /// Re-score quantized matches with the original vectors (Qdrant rescores by default)
+    #[arg(long)]
+    pub rescore: Option<bool>,
+
+    /// Fetch this many times more quantized candidates before re-scoring them
+    #[arg(long)]
+    pub oversampling: Option<f64>,
+
+    /// Keep the original vectors on disk instead of in RAM
+    #[arg(long)]
+    pub on_disk: bool,
+
+    /// Keep payloads on disk instead of in RAM
+    #[arg(long)]
+    pub on_disk_payload: bool,
+
+    /// Number of shards to split the collection into
+    #[arg(long)]
+    pub shards: Option<u32>,
+}
+
+impl StorageSettings {
+    /// These settings, falling back to `defaults` for anything unset
+    pub fn or(self, defaults: &StorageSettings) -> StorageSettings {
+        StorageSettings {
+            distance: self.distance.or(defaults.distance),
+            hnsw_m: self.hnsw_m.or(defaults.hnsw_m),
+            hnsw_ef_construct: self.hnsw_ef_construct.or(defaults.hnsw_ef_construct),
+            hnsw_ef: self.hnsw_ef.or(defaults.hnsw_ef),
+            quantization: self.quantization.or(defaults.quantization),
+            compression: self.compression.or(defaults.compression),
+            rescore: self.rescore.or(defaults.rescore),
+            oversampling: self.oversampling.or(defaults.oversampling),
+            on_disk: self.on_disk || defaults.on_disk,
+            on_disk_payload: self.on_disk_payload || defaults.on_disk_payload,
+            shards: self.shards.or(defaults.shards),
+        }
+    }
+
+    /// Search parameters to record with the collection
+    pub fn search_params(&self) -> SearchParams {
+        SearchParams {
+            hnsw_ef: self.hnsw_ef,
+            rescore: self.rescore,
+            oversampling: self.oversampling,
+        }
+    }
+
+    /// Body of the request creating a collection of `vector_size` dimensions
+    fn create_request(&self, vector_size: usize) -> Result<serde_json::Value> {
+        let mut request = serde_json::json!({
+            "vectors": {
+                "size": vector_size,
+                "distance": self.distance.unwrap_or_default().as_str(),
+                "on_disk": self.on_disk,
+            },
+            "on_disk_payload": self.on_disk_payload,
+        });
+
+        if self.hnsw_m.is_some() || self.hnsw_ef_construct.is_some() {
+            let mut hnsw = serde_json::Map::new();
+            if let Some(m) = self.hnsw_m {
+                hnsw.insert("m".to_string(), m.into());
+            }
+            if let Some(ef_construct) = self.hnsw_ef_construct {
+                hnsw.insert("ef_construct".to_string(), ef_construct.into());
+            }
+            request["hnsw_config"] = hnsw.into();
+        }
+
+        if self.compression.is_some() && self.quantization != Some(Quantization::Product) {
+            anyhow::bail!("--compression only applies to --quantization product");
+        }
+        if let Some(quantization) = self.quantization {
+            request["quantization_config"] = match quantization {
+                Quantization::Scalar => serde_json::json!({
+                    "scalar": { "type": "int8", "always_ram": true }
+                }),
+                Quantization::Binary => serde_json::json!({
+                    "binary": { "always_ram": true }
+                }),
+                Quantization::Product => {
+                    let compression = self.compression.unwrap_or(16);
+                    if !PRODUCT_COMPRESSIONS.contains(&compression) {
+                        anyhow::bail!(
+                            "Unsupported product quantization compression {}; use 4, 8, 16, 32 or 64",
+                            compression
+                        );
+                    }
+                    serde_json::json!({
+                        "product": { "compression": format!("x{}", compression), "always_ram": true }
+                    })
+                }
+            };
+        }
+
+        if let Some(shards) = self.shards {
+            request["shard_number"] = shards.into();
+        }
+
+        Ok(request)
+    }
+}
+
+/// Search-time parameters of a collection, recorded in its metadata
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SearchParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hnsw_ef: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rescore: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oversampling: Option<f64>,
+}
+
+impl SearchParams {
+    /// These parameters, falling back to `defaults` for anything unset
+    pub fn or(self, defaults: &SearchParams) -> SearchParams {
+        SearchParams {
+            hnsw_ef: self.hnsw_ef.or(defaults.hnsw_ef),
+            rescore: self.rescore.or(defaults.rescore),
+            oversampling: self.oversampling.or(defaults.oversampling),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == SearchParams::default()
+    }
+
+    /// The `params` of a search request
+    fn to_request(&self) -> serde_json::Value {
+        let mut params = serde_json::Map::new();
+        if let Some(ef) = self.hnsw_ef {
+            params.insert("hnsw_ef".to_string(), ef.into());
+        }
+        let mut quantization = serde_json::Map::new();
+        if let Some(rescore) = self.rescore {
+            quantization.insert("rescore".to_string(), rescore.into());
+        }
+        if let Some(oversampling) = self.oversampling {
+            quantization.insert("oversampling".to_string(), oversampling.into());
+        }
+        if !quantization.is_empty() {
+            params.insert("quantization".to_string(), quantization.into());
+        }
+        params.into()
+    }
+}
+
+/// Consistency of writes across a distributed Qdrant cluster
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq)]
+pub enum WriteOrdering {
+    /// Writes may be applied in any order (Qdrant's default, fastest)
+    #[default]
+    Weak,
+    /// Writes go through a dynamically elected leader
+    Medium,
+    /// Writes go through the permanent leader
+    Strong,
+}
+
+impl WriteOrdering {
+    fn as_str(&self) -> &'static str {
+        match self {
+            WriteOrdering::Weak => "weak",
+            WriteOrdering::Medium => "medium",
+            WriteOrdering::Strong => "strong",
+        }
+    }
+}
+
+/// How points are written
+struct WriteOptions {
+    /// Maximum points per upsert request
+    batch_size: usize,
+    /// Upsert requests in flight at once
+    concurrency: usize,
+    /// Whether requests return only once the write is applied and searchable
+    wait: bool,
+    ordering: WriteOrdering,
+}
+
+impl WriteOptions {
+    /// Query string for write requests
+    fn query(&self) -> String {
+        format!("wait={}&ordering={}", self.wait, self.ordering.as_str())
+    }
+}
+
+/// How points are written, searched and scrolled; everything else uses REST
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq)]
+pub enum QdrantTransport {
+    /// JSON over HTTP
+    #[default]
+    Rest,
+    /// Protobuf over gRPC, faster for bulk ingest (requires the grpc feature)
+    Grpc,
 }
 
 pub struct QdrantClient {
     client: reqwest::Client,
     base_url: String,
+    retries: u32,
+    writes: WriteOptions,
+    /// Metric of each collection searched so far, to turn its scores into similarities
+    distances: std::sync::Mutex<HashMap<String, Distance>>,
+    #[cfg(feature = "grpc")]
+    grpc: Option<grpc::GrpcClient>,
 }
 
 impl QdrantClient {
-    pub fn new(base_url: Option<&str>) -> Self {
-        Self {
-            client: reqwest::Client::new(),
-            base_url: base_url.unwrap_or(DEFAULT_QDRANT_URL).to_string(),
-        }
-    }
+    /// Connect to the Qdrant configured on the command line, with its API key,
+    /// CA certificates, timeout and retries
+    pub fn from_cli(cli: &Cli) -> Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(cli.qdrant_timeout))
+            .connect_timeout(CONNECT_TIMEOUT);
 
-    /// Ensure a collection exists with the right vector size
-    pub async fn ensure_collection(&self, collection: &str, vector_size: usize) -> Result<()> {
-        // Check if collection exists
-        let response = self
-            .client
-            .get(format!("{}/collections/{}", self.base_url, collection))
-            .send()
-            .await;
+        if let Some(api_key) = &cli.qdrant_api_key {
+            let mut value = reqwest::header::HeaderValue::from_str(api_key)
+                .context("Invalid Qdrant API key")?;
+            value.set_sensitive(true);
+            let mut headers = reqwest::header::HeaderMap::new();
+            headers.insert("api-key", value);
+            builder = builder.default_headers(headers);
+        }
 
-        if let Ok(resp) = response {
-            if resp.status().is_success() {
-                return Ok(());
+        if let Some(path) = &cli.qdrant_ca_cert {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read CA certificate {}", path.display()))?;
+            let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("Invalid CA certificate {}", path.display()))?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
             }
         }
 
-        // Create collection
-        let request = CreateCollectionRequest {
-            vectors: VectorConfig {
-                size: vector_size,
-                distance: "Cosine".to_string(),
+        #[cfg(not(feature = "grpc"))]
+        if cli.qdrant_transport == QdrantTransport::Grpc {
+            anyhow::bail!(
+                "This build of know has no gRPC support. Rebuild it with --features grpc, \
+                or use --qdrant-transport rest."
+            );
+        }
+
+        Ok(Self {
+            client: builder.build().context("Failed to create Qdrant client")?,
+            base_url: cli.qdrant_url.trim_end_matches('/').to_string(),
+            retries: cli.qdrant_retries,
+            writes: WriteOptions {
+                batch_size: cli.upsert_batch_size.max(1),
+                concurrency: cli.upsert_concurrency.max(1),
+                wait: !cli.no_wait,
+                ordering: cli.write_ordering,
             },
-        };
+            distances: std::sync::Mutex::new(HashMap::new()),
+            #[cfg(feature = "grpc")]
+            grpc: match cli.qdrant_transport {
+                QdrantTransport::Rest => None,
+                QdrantTransport::Grpc => Some(grpc::GrpcClient::from_cli(cli)?),
+            },
+        })
+    }
 
-        self.client
-            .put(format!("{}/collections/{}", self.base_url, collection))
+    fn request(&self, method: reqwest::Method, url: String) -> Request {
+        Request {
+            builder: self.client.request(method, url),
+            retries: self.retries,
+        }
+    }
+
+    fn get(&self, url: String) -> Request {
+        self.request(reqwest::Method::GET, url)
+    }
+
+    fn put(&self, url: String) -> Request {
+        self.request(reqwest::Method::PUT, url)
+    }
+
+    fn post(&self, url: String) -> Request {
+        self.request(reqwest::Method::POST, url)
+    }
+
+    fn delete(&self, url: String) -> Request {
+        self.request(reqwest::Method::DELETE, url)
+    }
+
+    /// Create a collection from a Qdrant create request and index the payload
+    /// fields used in filters
+    async fn create_collection(&self, collection: &str, request: &serde_json::Value) -> Result<()> {
+        self.put(format!("{}/collections/{}", self.base_url, collection))
             .json(&request)
-            .send()
-            .await
-            .context("Failed to create collection")?
-            .error_for_status()
-            .context("Failed to create collection")?;
+            .send("Failed to create collection")
+            .await?;
+
+        // Index the payload fields used in filters so lookups don't scan every point
+        for (field, schema) in PAYLOAD_INDEXES {
+            self.put(format!(
+                "{}/collections/{}/index",
+                self.base_url, collection
+            ))
+            .json(&serde_json::json!({ "field_name": field, "field_schema": schema }))
+            .send("Failed to create payload index")
+            .await?;
+        }
 
         Ok(())
     }
@@ -118,31 +920,175 @@ impl QdrantClient {
         chunk: &DocumentChunk,
         embedding: Vec<f32>,
     ) -> Result<()> {
-        let request = UpsertPointsRequest {
-            points: vec![Point {
-                id: chunk.id.clone(),
-                vector: embedding,
-                payload: PointPayload {
-                    content: chunk.content.clone(),
-                    source: chunk.source.clone(),
-                },
-            }],
+        let point = Point {
+            id: chunk.id.clone(),
+            vector: embedding,
+            payload: PointPayload::from_chunk(chunk),
         };
+        self.upsert_points(collection, &[point]).await
+    }
 
-        self.client
-            .put(format!("{}/collections/{}/points", self.base_url, collection))
-            .json(&request)
-            .send()
+    /// Write points in a single request
+    async fn upsert_points(&self, collection: &str, points: &[Point]) -> Result<()> {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            return grpc.upsert(collection, points, &self.writes).await;
+        }
+
+        self.put(format!(
+            "{}/collections/{}/points?{}",
+            self.base_url,
+            collection,
+            self.writes.query()
+        ))
+        .json(&UpsertPointsRequest { points })
+        .send("Failed to upsert points")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Metric of a collection's vectors, looked up once per collection
+    async fn distance(&self, collection: &str) -> Result<Distance> {
+        if let Some(&distance) = self.distances.lock().unwrap().get(collection) {
+            return Ok(distance);
+        }
+
+        let info = self
+            .collection_info(collection)
+            .await?
+            .with_context(|| format!("Collection '{}' not found", collection))?;
+        let distance = info
+            .distance
+            .and_then(|d| Distance::from_str(&d, true).ok())
+            .unwrap_or_default();
+        self.distances
+            .lock()
+            .unwrap()
+            .insert(collection.to_string(), distance);
+        Ok(distance)
+    }
+
+    /// The collection an alias points at, or `name` itself if it isn't an alias
+    async fn resolve_alias(&self, name: &str) -> Result<String> {
+        Ok(self
+            .aliases()
+            .await?
+            .into_iter()
+            .find(|a| a.name == name)
+            .map_or_else(|| name.to_string(), |a| a.collection))
+    }
+
+    /// Read the metadata record kept in a point of the metadata collection
+    async fn metadata_point(&self, id: &str) -> Result<Option<CollectionMetadata>> {
+        #[derive(Deserialize)]
+        struct PointResponse {
+            result: PointResult,
+        }
+
+        #[derive(Deserialize)]
+        struct PointResult {
+            payload: MetadataPayload,
+        }
+
+        #[derive(Deserialize)]
+        struct MetadataPayload {
+            metadata: CollectionMetadata,
+        }
+
+        let response = self
+            .get(format!(
+                "{}/collections/{}/points/{}",
+                self.base_url, METADATA_COLLECTION, id
+            ))
+            .send_raw("Failed to get collection metadata")
+            .await?;
+
+        // Qdrant answers 404 for a missing collection as well as a missing point
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let point: PointResponse = check_status(response, "Failed to get collection metadata")
+            .await?
+            .json()
             .await
-            .context("Failed to upsert point")?
-            .error_for_status()
-            .context("Failed to upsert point")?;
+            .context("Failed to parse collection metadata")?;
+
+        Ok(Some(point.result.payload.metadata))
+    }
+
+    /// Create the collection holding metadata records if it doesn't exist yet
+    async fn ensure_metadata_collection(&self) -> Result<()> {
+        if self.collection_info(METADATA_COLLECTION).await?.is_some() {
+            return Ok(());
+        }
+
+        // Records are only ever looked up by ID, so a one-dimensional vector will do
+        let created = self
+            .put(format!(
+                "{}/collections/{}",
+                self.base_url, METADATA_COLLECTION
+            ))
+            .json(&serde_json::json!({ "vectors": { "size": 1, "distance": "Dot" } }))
+            .send("Failed to create metadata collection")
+            .await;
+
+        // Another process may have created it in the meantime
+        match created {
+            Ok(_) => Ok(()),
+            Err(_) if self.collection_info(METADATA_COLLECTION).await?.is_some() => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update_aliases(&self, actions: Vec<serde_json::Value>) -> Result<()> {
+        self.post(format!("{}/collections/aliases", self.base_url))
+            .json(&serde_json::json!({ "actions": actions }))
+            .send("Failed to update aliases")
+            .await?;
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl VectorStore for QdrantClient {
+    /// Ensure a collection exists with the right vector size, creating it
+    /// with `settings` if it doesn't
+    async fn ensure_collection(
+        &self,
+        collection: &str,
+        vector_size: usize,
+        settings: &StorageSettings,
+    ) -> Result<()> {
+        if let Some(info) = self.collection_info(collection).await? {
+            if let Some(existing) = info.vector_size {
+                check_vector_size(collection, existing, vector_size)?;
+            }
+            return Ok(());
+        }
+
+        self.create_collection(collection, &settings.create_request(vector_size)?)
+            .await
+    }
 
-    /// Batch upsert multiple chunks
-    pub async fn upsert_batch(
+    /// Create a collection configured like an existing one, but holding
+    /// `vector_size`-dimensional vectors
+    async fn create_collection_like(
+        &self,
+        collection: &str,
+        like: &CollectionInfo,
+        vector_size: usize,
+    ) -> Result<()> {
+        let mut request = like.create_request();
+        request["vectors"]["size"] = vector_size.into();
+        self.create_collection(collection, &request).await
+    }
+
+    /// Batch upsert multiple chunks, split into requests of at most
+    /// `--upsert-batch-size` points sent a few at a time
+    async fn upsert_batch(
         &self,
         collection: &str,
         chunks: &[DocumentChunk],
@@ -154,81 +1100,182 @@ impl QdrantClient {
             .map(|(chunk, embedding)| Point {
                 id: chunk.id.clone(),
                 vector: embedding,
-                payload: PointPayload {
-                    content: chunk.content.clone(),
-                    source: chunk.source.clone(),
-                },
+                payload: PointPayload::from_chunk(chunk),
             })
             .collect();
 
-        let request = UpsertPointsRequest { points };
-
-        self.client
-            .put(format!("{}/collections/{}/points", self.base_url, collection))
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to batch upsert points")?
-            .error_for_status()
-            .context("Failed to batch upsert points")?;
+        let batch_count = points.len().div_ceil(self.writes.batch_size);
+        // Built in a loop rather than with map, which async_trait's Send
+        // bound can't see through
+        let mut requests = Vec::with_capacity(batch_count);
+        for (i, batch) in points.chunks(self.writes.batch_size).enumerate() {
+            requests.push(async move {
+                self.upsert_points(collection, batch)
+                    .await
+                    .with_context(|| format!("Failed to upsert batch {} of {}", i + 1, batch_count))
+            });
+        }
+        futures::stream::iter(requests)
+            .buffer_unordered(self.writes.concurrency)
+            .try_collect::<Vec<_>>()
+            .await?;
 
         Ok(())
     }
 
-    /// Search for similar documents
-    pub async fn search(
+    /// Search for similar points matching a filter, keeping IDs and similarity scores
+    async fn search_scored(
         &self,
         collection: &str,
         query_embedding: Vec<f32>,
         limit: usize,
-    ) -> Result<Vec<DocumentChunk>> {
+        filter: &Filter,
+        with_vector: bool,
+        params: &SearchParams,
+    ) -> Result<Vec<ScoredChunk>> {
+        let distance = self.distance(collection).await?;
+
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            let mut hits = grpc
+                .search(
+                    collection,
+                    query_embedding,
+                    limit,
+                    filter,
+                    with_vector,
+                    params,
+                )
+                .await?;
+            for hit in &mut hits {
+                hit.score = distance.similarity(hit.score);
+            }
+            return Ok(hits);
+        }
+
         let request = SearchRequest {
             vector: query_embedding,
             limit,
             with_payload: true,
+            with_vector,
+            filter,
+            params: (!params.is_empty()).then(|| params.to_request()),
         };
 
         let response: SearchResponse = self
-            .client
             .post(format!(
                 "{}/collections/{}/points/search",
                 self.base_url, collection
             ))
             .json(&request)
-            .send()
-            .await
-            .context("Failed to search")?
+            .send("Failed to search")
+            .await?
             .json()
             .await
             .context("Failed to parse search response")?;
 
-        let chunks = response
+        let hits = response
             .result
             .into_iter()
             .filter_map(|r| {
-                r.payload.map(|p| DocumentChunk {
-                    id: String::new(), // ID not needed for search results
-                    content: p.content,
-                    source: p.source,
+                let score = distance.similarity(r.score);
+                let vector = r.vector;
+                let id = point_id_to_string(r.id);
+                r.payload.map(|p| ScoredChunk {
+                    chunk: p.into_chunk(id),
+                    score,
+                    vector,
+                    collection: None,
+                    fused_score: None,
                 })
             })
             .collect();
 
+        Ok(hits)
+    }
+
+    /// Fetch every point matching a filter, following scroll pagination
+    async fn scroll(&self, collection: &str, filter: &Filter) -> Result<Vec<DocumentChunk>> {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            return grpc.scroll(collection, filter).await;
+        }
+
+        let mut chunks = Vec::new();
+        let mut offset = None;
+
+        loop {
+            let request = ScrollRequest {
+                filter,
+                limit: SCROLL_PAGE_SIZE,
+                with_payload: true,
+                with_vector: false,
+                offset: offset.take(),
+            };
+
+            let response: ScrollResponse = self
+                .post(format!(
+                    "{}/collections/{}/points/scroll",
+                    self.base_url, collection
+                ))
+                .json(&request)
+                .send("Failed to scroll points")
+                .await?
+                .json()
+                .await
+                .context("Failed to parse scroll response")?;
+
+            chunks.extend(response.result.points.into_iter().filter_map(|point| {
+                let id = point_id_to_string(point.id);
+                point.payload.map(|p| p.into_chunk(id))
+            }));
+
+            match response.result.next_page_offset {
+                Some(next) if !next.is_null() => offset = Some(next),
+                _ => break,
+            }
+        }
+
         Ok(chunks)
     }
 
+    /// Record the other sources a chunk was found in
+    async fn set_sources(
+        &self,
+        collection: &str,
+        id: &str,
+        source: &str,
+        other_sources: &[String],
+    ) -> Result<()> {
+        let request = SetPayloadRequest {
+            payload: serde_json::json!({ "source": source, "other_sources": other_sources }),
+            points: vec![id.to_string()],
+        };
+
+        self.post(format!(
+            "{}/collections/{}/points/payload?{}",
+            self.base_url,
+            collection,
+            self.writes.query()
+        ))
+        .json(&request)
+        .send("Failed to set point payload")
+        .await?;
+
+        Ok(())
+    }
+
     /// Get collection info
-    pub async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>> {
+    async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>> {
         let response = self
-            .client
             .get(format!("{}/collections/{}", self.base_url, collection))
-            .send()
-            .await
-            .context("Failed to get collection info")?;
+            .send_raw("Failed to get collection info")
+            .await?;
 
-        if !response.status().is_success() {
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
             return Ok(None);
         }
+        let response = check_status(response, "Failed to get collection info").await?;
 
         #[derive(Deserialize)]
         struct InfoResponse {
@@ -239,29 +1286,377 @@ impl QdrantClient {
         struct CollectionResult {
             points_count: usize,
             indexed_vectors_count: usize,
+            config: serde_json::Value,
         }
 
         let info: InfoResponse = response.json().await?;
+        let config = info.result.config;
+        let vectors = config.pointer("/params/vectors");
+        let number = |pointer: &str| {
+            config
+                .pointer(pointer)
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize)
+        };
+        let flag =
+            |value: Option<&serde_json::Value>| value.and_then(|v| v.as_bool()).unwrap_or(false);
 
         Ok(Some(CollectionInfo {
             points_count: info.result.points_count,
             indexed_vectors_count: info.result.indexed_vectors_count,
+            vector_size: number("/params/vectors/size"),
+            distance: vectors
+                .and_then(|v| v.get("distance"))
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            hnsw_m: number("/hnsw_config/m"),
+            hnsw_ef_construct: number("/hnsw_config/ef_construct"),
+            quantization: config
+                .get("quantization_config")
+                .and_then(|q| q.as_object())
+                .and_then(|q| q.keys().next().cloned()),
+            on_disk: flag(vectors.and_then(|v| v.get("on_disk"))),
+            on_disk_payload: flag(config.pointer("/params/on_disk_payload")),
+            shards: number("/params/shard_number"),
+            config,
         }))
     }
 
-    /// Delete a collection
-    pub async fn delete_collection(&self, collection: &str) -> Result<()> {
-        self.client
-            .delete(format!("{}/collections/{}", self.base_url, collection))
-            .send()
+    /// Names of all collections, sorted
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct ListResponse {
+            result: ListResult,
+        }
+
+        #[derive(Deserialize)]
+        struct ListResult {
+            collections: Vec<CollectionName>,
+        }
+
+        #[derive(Deserialize)]
+        struct CollectionName {
+            name: String,
+        }
+
+        let list: ListResponse = self
+            .get(format!("{}/collections", self.base_url))
+            .send("Failed to list collections")
+            .await?
+            .json()
+            .await
+            .context("Failed to parse collection list")?;
+
+        let mut names: Vec<String> = list
+            .result
+            .collections
+            .into_iter()
+            .map(|c| c.name)
+            .filter(|name| name != METADATA_COLLECTION)
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Payloads of the points matching a filter, with only the given fields
+    async fn scroll_fields(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        fields: &[&str],
+    ) -> Result<Vec<serde_json::Value>> {
+        let mut payloads = Vec::new();
+        let mut offset: Option<serde_json::Value> = None;
+
+        loop {
+            let request = serde_json::json!({
+                "filter": filter,
+                "limit": SCROLL_PAGE_SIZE,
+                "with_payload": { "include": fields },
+                "with_vector": false,
+                "offset": offset,
+            });
+
+            let response: serde_json::Value = self
+                .post(format!(
+                    "{}/collections/{}/points/scroll",
+                    self.base_url, collection
+                ))
+                .json(&request)
+                .send("Failed to scroll points")
+                .await?
+                .json()
+                .await
+                .context("Failed to parse scroll response")?;
+
+            let points = response
+                .pointer("/result/points")
+                .and_then(|p| p.as_array())
+                .cloned()
+                .unwrap_or_default();
+            payloads.extend(
+                points
+                    .into_iter()
+                    .filter_map(|mut point| point.get_mut("payload").map(serde_json::Value::take)),
+            );
+
+            match response.pointer("/result/next_page_offset") {
+                Some(next) if !next.is_null() => offset = Some(next.clone()),
+                _ => break,
+            }
+        }
+
+        Ok(payloads)
+    }
+
+    /// Vectors of the points with these IDs; IDs that don't exist are left out
+    async fn vectors(&self, collection: &str, ids: &[String]) -> Result<HashMap<String, Vec<f32>>> {
+        #[derive(Deserialize)]
+        struct PointsResponse {
+            result: Vec<PointVector>,
+        }
+
+        #[derive(Deserialize)]
+        struct PointVector {
+            id: serde_json::Value,
+            vector: Option<Vec<f32>>,
+        }
+
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let response: PointsResponse = self
+            .post(format!(
+                "{}/collections/{}/points",
+                self.base_url, collection
+            ))
+            .json(&serde_json::json!({
+                "ids": ids,
+                "with_payload": false,
+                "with_vector": true,
+            }))
+            .send("Failed to retrieve points")
+            .await?
+            .json()
+            .await
+            .context("Failed to parse points response")?;
+
+        Ok(response
+            .result
+            .into_iter()
+            .filter_map(|p| Some((point_id_to_string(p.id), p.vector?)))
+            .collect())
+    }
+
+    /// Delete every point matching a filter
+    async fn delete_points(&self, collection: &str, filter: &Filter) -> Result<()> {
+        self.post(format!(
+            "{}/collections/{}/points/delete?{}",
+            self.base_url,
+            collection,
+            self.writes.query()
+        ))
+        .json(&serde_json::json!({ "filter": filter }))
+        .send("Failed to delete points")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Copy every point of a collection, metadata included, into a new
+    /// collection with the same vector parameters
+    async fn copy_collection(&self, from: &str, to: &str) -> Result<usize> {
+        let info = self
+            .collection_info(from)
+            .await?
+            .with_context(|| format!("Collection '{}' not found", from))?;
+        if self.collection_info(to).await?.is_some() {
+            anyhow::bail!("Collection '{}' already exists", to);
+        }
+
+        self.create_collection(to, &info.create_request()).await?;
+
+        let mut copied = 0;
+        let mut offset: Option<serde_json::Value> = None;
+        loop {
+            let request = serde_json::json!({
+                "filter": Filter::default(),
+                "limit": SCROLL_PAGE_SIZE,
+                "with_payload": true,
+                "with_vector": true,
+                "offset": offset,
+            });
+
+            let response: serde_json::Value = self
+                .post(format!(
+                    "{}/collections/{}/points/scroll",
+                    self.base_url, from
+                ))
+                .json(&request)
+                .send("Failed to scroll points")
+                .await?
+                .json()
+                .await
+                .context("Failed to parse scroll response")?;
+
+            let points = response
+                .pointer("/result/points")
+                .cloned()
+                .unwrap_or_default();
+            let count = points.as_array().map_or(0, Vec::len);
+            if count > 0 {
+                self.put(format!(
+                    "{}/collections/{}/points?{}",
+                    self.base_url,
+                    to,
+                    self.writes.query()
+                ))
+                .json(&serde_json::json!({ "points": points }))
+                .send("Failed to copy points")
+                .await?;
+                copied += count;
+            }
+
+            match response.pointer("/result/next_page_offset") {
+                Some(next) if !next.is_null() => offset = Some(next.clone()),
+                _ => break,
+            }
+        }
+
+        if let Some(metadata) = self.get_metadata(from).await? {
+            self.set_metadata(to, &metadata).await?;
+        }
+
+        Ok(copied)
+    }
+
+    /// All aliases, sorted by alias name
+    async fn aliases(&self) -> Result<Vec<Alias>> {
+        #[derive(Deserialize)]
+        struct AliasesResponse {
+            result: AliasesResult,
+        }
+
+        #[derive(Deserialize)]
+        struct AliasesResult {
+            aliases: Vec<AliasDescription>,
+        }
+
+        #[derive(Deserialize)]
+        struct AliasDescription {
+            alias_name: String,
+            collection_name: String,
+        }
+
+        let response: AliasesResponse = self
+            .get(format!("{}/aliases", self.base_url))
+            .send("Failed to list aliases")
+            .await?
+            .json()
             .await
-            .context("Failed to delete collection")?;
+            .context("Failed to parse alias list")?;
+
+        let mut aliases: Vec<Alias> = response
+            .result
+            .aliases
+            .into_iter()
+            .map(|a| Alias {
+                name: a.alias_name,
+                collection: a.collection_name,
+            })
+            .collect();
+        aliases.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(aliases)
+    }
+
+    /// Point an alias at a collection, replacing whatever it pointed at before
+    /// in a single atomic step
+    async fn set_alias(&self, alias: &str, collection: &str) -> Result<()> {
+        let mut actions = Vec::new();
+        if self.aliases().await?.iter().any(|a| a.name == alias) {
+            actions.push(serde_json::json!({ "delete_alias": { "alias_name": alias } }));
+        }
+        actions.push(serde_json::json!({
+            "create_alias": { "alias_name": alias, "collection_name": collection }
+        }));
+        self.update_aliases(actions).await
+    }
+
+    /// Remove an alias; the collection it points at is kept
+    async fn delete_alias(&self, alias: &str) -> Result<()> {
+        self.update_aliases(vec![
+            serde_json::json!({ "delete_alias": { "alias_name": alias } }),
+        ])
+        .await
+    }
+
+    /// Read the metadata record of a collection, if there is one
+    async fn get_metadata(&self, collection: &str) -> Result<Option<CollectionMetadata>> {
+        let collection = self.resolve_alias(collection).await?;
+        self.metadata_point(&metadata_point_id(&collection)).await
+    }
+
+    /// Store the metadata record of an existing collection, replacing any previous one
+    async fn set_metadata(&self, collection: &str, metadata: &CollectionMetadata) -> Result<()> {
+        let collection = self.resolve_alias(collection).await?;
+        if self.collection_info(&collection).await?.is_none() {
+            anyhow::bail!("Collection '{}' not found", collection);
+        }
+        self.ensure_metadata_collection().await?;
+
+        let request = serde_json::json!({
+            "points": [{
+                "id": metadata_point_id(&collection),
+                "vector": [1.0],
+                "payload": { "collection": collection, "metadata": metadata },
+            }]
+        });
+
+        self.put(format!(
+            "{}/collections/{}/points?{}",
+            self.base_url,
+            METADATA_COLLECTION,
+            self.writes.query()
+        ))
+        .json(&request)
+        .send("Failed to store collection metadata")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete a collection and its metadata record
+    async fn delete_collection(&self, collection: &str) -> Result<()> {
+        // A collection that is already gone is fine, any other failure isn't
+        let response = self
+            .delete(format!("{}/collections/{}", self.base_url, collection))
+            .send_raw("Failed to delete collection")
+            .await?;
+        if response.status() != reqwest::StatusCode::NOT_FOUND {
+            check_status(response, "Failed to delete collection").await?;
+        }
+
+        // The metadata collection may not exist yet, so a 404 is fine here too
+        let response = self
+            .post(format!(
+                "{}/collections/{}/points/delete?{}",
+                self.base_url,
+                METADATA_COLLECTION,
+                self.writes.query()
+            ))
+            .json(&serde_json::json!({ "points": [metadata_point_id(collection)] }))
+            .send_raw("Failed to delete collection metadata")
+            .await?;
+        if response.status() != reqwest::StatusCode::NOT_FOUND {
+            check_status(response, "Failed to delete collection metadata").await?;
+        }
 
         Ok(())
     }
 
     /// Check if qdrant is available
-    pub async fn is_available(&self) -> bool {
+    async fn is_available(&self) -> bool {
         self.client
             .get(format!("{}/readyz", self.base_url))
             .timeout(std::time::Duration::from_secs(2))
@@ -276,19 +1671,47 @@ pub struct CollectionInfo {
     pub points_count: usize,
     #[allow(dead_code)]
     pub indexed_vectors_count: usize,
+    /// Dimensions of the collection's vectors
+    pub vector_size: Option<usize>,
+    /// Similarity metric, e.g. Cosine
+    pub distance: Option<String>,
+    /// Links per node of the HNSW graph
+    pub hnsw_m: Option<usize>,
+    /// Neighbours considered while building the HNSW graph
+    pub hnsw_ef_construct: Option<usize>,
+    /// Kind of quantization, e.g. scalar
+    pub quantization: Option<String>,
+    /// Whether the original vectors are kept on disk
+    pub on_disk: bool,
+    /// Whether payloads are kept on disk
+    pub on_disk_payload: bool,
+    pub shards: Option<usize>,
+    /// Collection configuration as the store reports it
+    pub config: serde_json::Value,
 }
 
-/// Clean/delete a collection
-pub async fn clean(collection: &str) -> Result<()> {
-    let client = QdrantClient::new(None);
-
-    if !client.is_available().await {
-        println!("Qdrant is not available. Run 'know up' first.");
-        return Ok(());
+impl CollectionInfo {
+    /// Request creating a collection configured like this one
+    fn create_request(&self) -> serde_json::Value {
+        let mut request = serde_json::Map::new();
+        let fields = [
+            ("vectors", "/params/vectors"),
+            ("shard_number", "/params/shard_number"),
+            ("on_disk_payload", "/params/on_disk_payload"),
+            ("hnsw_config", "/hnsw_config"),
+            ("quantization_config", "/quantization_config"),
+        ];
+        for (field, pointer) in fields {
+            if let Some(value) = self.config.pointer(pointer).filter(|v| !v.is_null()) {
+                request.insert(field.to_string(), value.clone());
+            }
+        }
+        request.into()
     }
+}
 
-    client.delete_collection(collection).await?;
-    println!("Collection '{}' deleted.", collection);
-
-    Ok(())
+/// An alternative name for a collection
+pub struct Alias {
+    pub name: String,
+    pub collection: String,
 }
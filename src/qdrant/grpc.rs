@@ -0,0 +1,305 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use qdrant_client::qdrant::point_id::PointIdOptions;
+use qdrant_client::qdrant::r#match::MatchValue;
+use qdrant_client::qdrant::vector_output::Vector;
+use qdrant_client::qdrant::{
+    PointId, PointStruct, QuantizationSearchParamsBuilder, Range, ScrollPointsBuilder,
+    SearchParamsBuilder, SearchPointsBuilder, UpsertPointsBuilder, WriteOrderingType,
+};
+use qdrant_client::{Payload, Qdrant, QdrantError};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+use super::{
+    Condition, DocumentChunk, Filter, Point, PointPayload, ScoredChunk, SearchParams, WriteOptions,
+    WriteOrdering, CONNECT_TIMEOUT, INITIAL_BACKOFF, MAX_BACKOFF, SCROLL_PAGE_SIZE,
+};
+use crate::cli::Cli;
+
+/// Qdrant's gRPC API, used instead of REST for the calls that move vectors in bulk
+pub(super) struct GrpcClient {
+    client: Qdrant,
+    retries: u32,
+}
+
+impl GrpcClient {
+    pub(super) fn from_cli(cli: &Cli) -> Result<Self> {
+        if cli.qdrant_ca_cert.is_some() {
+            anyhow::bail!(
+                "--qdrant-ca-cert is not supported with --qdrant-transport grpc, which trusts \
+                the system's root certificates only"
+            );
+        }
+
+        let client = Qdrant::from_url(&cli.qdrant_grpc_url)
+            .api_key(cli.qdrant_api_key.clone())
+            .timeout(Duration::from_secs(cli.qdrant_timeout))
+            .connect_timeout(CONNECT_TIMEOUT)
+            .skip_compatibility_check()
+            .build()
+            .with_context(|| format!("Invalid Qdrant gRPC URL '{}'", cli.qdrant_grpc_url))?;
+
+        Ok(Self {
+            client,
+            retries: cli.qdrant_retries,
+        })
+    }
+
+    /// Make a call, retrying transient failures with exponential backoff
+    async fn retry<T, F, Fut>(&self, action: &str, call: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, QdrantError>>,
+    {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let error = match call().await {
+                Ok(result) => return Ok(result),
+                Err(e) if is_transient(&e) => e,
+                Err(e) => anyhow::bail!("{}: {}", action, describe(&e)),
+            };
+
+            if attempt >= self.retries {
+                anyhow::bail!(
+                    "{}: {} (after {} attempts)",
+                    action,
+                    describe(&error),
+                    attempt + 1
+                );
+            }
+            attempt += 1;
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    pub(super) async fn upsert(
+        &self,
+        collection: &str,
+        points: &[Point],
+        writes: &WriteOptions,
+    ) -> Result<()> {
+        let points = points
+            .iter()
+            .map(|point| {
+                let payload = Payload::try_from(serde_json::to_value(&point.payload)?)?;
+                Ok(PointStruct::new(
+                    point.id.clone(),
+                    point.vector.clone(),
+                    payload,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let request = UpsertPointsBuilder::new(collection, points)
+            .wait(writes.wait)
+            .ordering(qdrant_client::qdrant::WriteOrdering {
+                r#type: match writes.ordering {
+                    WriteOrdering::Weak => WriteOrderingType::Weak,
+                    WriteOrdering::Medium => WriteOrderingType::Medium,
+                    WriteOrdering::Strong => WriteOrderingType::Strong,
+                } as i32,
+            })
+            .build();
+        self.retry("Failed to upsert points", || {
+            self.client.upsert_points(request.clone())
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn search(
+        &self,
+        collection: &str,
+        query_embedding: Vec<f32>,
+        limit: usize,
+        filter: &Filter,
+        with_vector: bool,
+        params: &SearchParams,
+    ) -> Result<Vec<ScoredChunk>> {
+        let mut request = SearchPointsBuilder::new(collection, query_embedding, limit as u64)
+            .filter(grpc_filter(filter)?)
+            .with_payload(true)
+            .with_vectors(with_vector);
+        if !params.is_empty() {
+            request = request.params(grpc_search_params(params));
+        }
+        let request = request.build();
+
+        let response = self
+            .retry("Failed to search", || {
+                self.client.search_points(request.clone())
+            })
+            .await?;
+
+        let mut hits = Vec::new();
+        for point in response.result {
+            let vector = point
+                .vectors
+                .as_ref()
+                .and_then(|vectors| vectors.get_vector())
+                .and_then(|vector| match vector {
+                    Vector::Dense(dense) => Some(dense.data),
+                    _ => None,
+                });
+            if let Some(chunk) = into_chunk(point.id, point.payload)? {
+                hits.push(ScoredChunk {
+                    chunk,
+                    score: point.score,
+                    vector,
+                    collection: None,
+                    fused_score: None,
+                });
+            }
+        }
+
+        Ok(hits)
+    }
+
+    pub(super) async fn scroll(
+        &self,
+        collection: &str,
+        filter: &Filter,
+    ) -> Result<Vec<DocumentChunk>> {
+        let filter = grpc_filter(filter)?;
+        let mut chunks = Vec::new();
+        let mut offset: Option<PointId> = None;
+
+        loop {
+            let mut request = ScrollPointsBuilder::new(collection)
+                .filter(filter.clone())
+                .limit(SCROLL_PAGE_SIZE as u32)
+                .with_payload(true)
+                .with_vectors(false);
+            if let Some(offset) = offset.take() {
+                request = request.offset(offset);
+            }
+            let request = request.build();
+
+            let response = self
+                .retry("Failed to scroll points", || {
+                    self.client.scroll(request.clone())
+                })
+                .await?;
+
+            for point in response.result {
+                if let Some(chunk) = into_chunk(point.id, point.payload)? {
+                    chunks.push(chunk);
+                }
+            }
+
+            match response.next_page_offset {
+                Some(next) => offset = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(chunks)
+    }
+}
+
+/// Whether a failed call may succeed if retried
+fn is_transient(error: &QdrantError) -> bool {
+    match error {
+        QdrantError::ResponseError { status } => matches!(
+            status.code(),
+            tonic::Code::Unavailable
+                | tonic::Code::DeadlineExceeded
+                | tonic::Code::Aborted
+                | tonic::Code::Internal
+        ),
+        QdrantError::ResourceExhaustedError { .. } => true,
+        _ => false,
+    }
+}
+
+/// Qdrant's explanation of a failed call, without the gRPC metadata
+fn describe(error: &QdrantError) -> String {
+    match error {
+        QdrantError::ResponseError { status } => {
+            format!("Qdrant returned {:?}: {}", status.code(), status.message())
+        }
+        other => other.to_string(),
+    }
+}
+
+fn grpc_filter(filter: &Filter) -> Result<qdrant_client::qdrant::Filter> {
+    Ok(qdrant_client::qdrant::Filter {
+        must: filter
+            .must
+            .iter()
+            .map(grpc_condition)
+            .collect::<Result<_>>()?,
+        must_not: filter
+            .must_not
+            .iter()
+            .map(grpc_condition)
+            .collect::<Result<_>>()?,
+        ..Default::default()
+    })
+}
+
+fn grpc_condition(condition: &Condition) -> Result<qdrant_client::qdrant::Condition> {
+    let key = condition.key.clone();
+    if let Some(range) = &condition.range {
+        return Ok(qdrant_client::qdrant::Condition::range(
+            key,
+            Range {
+                gte: Some(range.gte),
+                lte: Some(range.lte),
+                ..Default::default()
+            },
+        ));
+    }
+
+    let value: MatchValue = match condition.matches.as_ref().map(|m| &m.value) {
+        Some(serde_json::Value::String(s)) => s.clone().into(),
+        Some(serde_json::Value::Bool(b)) => (*b).into(),
+        Some(value) if value.is_i64() => value.as_i64().unwrap_or_default().into(),
+        _ => anyhow::bail!("Unsupported filter on '{}' for gRPC", key),
+    };
+    Ok(qdrant_client::qdrant::Condition::matches(key, value))
+}
+
+fn grpc_search_params(params: &SearchParams) -> SearchParamsBuilder {
+    let mut builder = SearchParamsBuilder::default();
+    if let Some(ef) = params.hnsw_ef {
+        builder = builder.hnsw_ef(ef as u64);
+    }
+    if params.rescore.is_some() || params.oversampling.is_some() {
+        let mut quantization = QuantizationSearchParamsBuilder::default();
+        if let Some(rescore) = params.rescore {
+            quantization = quantization.rescore(rescore);
+        }
+        if let Some(oversampling) = params.oversampling {
+            quantization = quantization.oversampling(oversampling);
+        }
+        builder = builder.quantization(quantization);
+    }
+    builder
+}
+
+/// The chunk stored in a point, if it has a payload
+fn into_chunk(
+    id: Option<PointId>,
+    payload: HashMap<String, qdrant_client::qdrant::Value>,
+) -> Result<Option<DocumentChunk>> {
+    if payload.is_empty() {
+        return Ok(None);
+    }
+    let payload: PointPayload =
+        serde_json::from_value(serde_json::Value::from(Payload::from(payload)))
+            .context("Failed to parse point payload")?;
+
+    let id = match id.and_then(|id| id.point_id_options) {
+        Some(PointIdOptions::Uuid(uuid)) => uuid,
+        Some(PointIdOptions::Num(num)) => num.to_string(),
+        None => String::new(),
+    };
+    Ok(Some(payload.into_chunk(id)))
+}
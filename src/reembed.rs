@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::backend::{create_backend, LlmBackend};
+use crate::cli::Cli;
+use crate::qdrant::{CollectionInfo, CollectionMetadata, Filter, PointKind};
+use crate::store::{self, VectorStore};
+
+/// Points embedded and written per request
+const BATCH_SIZE: usize = 64;
+
+/// Re-embed every chunk, summary and generated question of `from` into the new
+/// collection `to`, configured like `from` as described by `info`
+async fn rebuild(
+    store: &dyn VectorStore,
+    backend: &dyn LlmBackend,
+    from: &str,
+    info: &CollectionInfo,
+    to: &str,
+    mut metadata: CollectionMetadata,
+) -> Result<usize> {
+    let vector_size = backend.embed("test").await?.len();
+
+    let mut points = store.scroll(from, &Filter::chunks()).await?;
+    points.extend(
+        store
+            .scroll(from, &Filter::kind(PointKind::Summary))
+            .await?,
+    );
+
+    // Questions ingested before their text was stored can't be re-embedded
+    let (questions, untexted): (Vec<_>, Vec<_>) = store
+        .scroll(from, &Filter::kind(PointKind::Question))
+        .await?
+        .into_iter()
+        .partition(|point| point.question.is_some());
+    points.extend(questions);
+    if !untexted.is_empty() {
+        eprintln!(
+            "Warning: {} generated questions were not carried over; re-ingest with --questions to regenerate them.",
+            untexted.len()
+        );
+    }
+
+    store.create_collection_like(to, info, vector_size).await?;
+
+    let pb = ProgressBar::new(points.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
+            .expect("Invalid progress template")
+            .progress_chars("#>-"),
+    );
+    pb.set_message(format!("Embedding into {}", to));
+
+    for batch in points.chunks(BATCH_SIZE) {
+        let mut embeddings = Vec::with_capacity(batch.len());
+        for point in batch {
+            let text = point.question.as_deref().unwrap_or(&point.content);
+            let embedding = backend
+                .embed(text)
+                .await
+                .with_context(|| format!("Failed to embed a chunk of {}", point.source))?;
+            embeddings.push(embedding);
+        }
+        store.upsert_batch(to, batch, embeddings).await?;
+        pb.inc(batch.len() as u64);
+    }
+    pb.finish_with_message("Done!");
+
+    metadata.embed_model = Some(backend.embed_model().to_string());
+    metadata.backend = Some(backend.name().to_string());
+    metadata.dimensions = Some(vector_size);
+    metadata.version = Some(env!("CARGO_PKG_VERSION").to_string());
+    store.set_metadata(to, &metadata).await?;
+
+    Ok(points.len())
+}
+
+/// Lowercase name fragment for an embedding model, e.g. `ai-mxbai-embed-large`
+fn model_slug(model: &str) -> String {
+    model
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase()
+}
+
+/// Migrate a collection to the current embedding model (`--embed-model`, or
+/// the backend's default).
+///
+/// With `to`, the re-embedded copy is written to that new collection and the
+/// original is left alone. An alias is switched atomically to a new
+/// collection; a plain collection is replaced in place once the copy is built.
+pub async fn reembed(cli: &Cli, to: Option<&str>) -> Result<()> {
+    let collection = cli.collection()?;
+    let store = store::create_store(cli).await?;
+
+    let info = store
+        .collection_info(collection)
+        .await?
+        .with_context(|| format!("Collection '{}' not found", collection))?;
+    let metadata = store.get_metadata(collection).await?.unwrap_or_default();
+
+    let backend = create_backend(cli).await?;
+    let embed_model = backend.embed_model().to_string();
+    if to.is_none() && metadata.embed_model.as_deref() == Some(embed_model.as_str()) {
+        println!(
+            "Collection '{}' is already embedded with '{}'.",
+            collection, embed_model
+        );
+        return Ok(());
+    }
+
+    let alias = store
+        .aliases()
+        .await?
+        .into_iter()
+        .find(|a| a.name == collection);
+    let target = match (to, &alias) {
+        (Some(to), _) => to.to_string(),
+        (None, Some(_)) => format!("{}-{}", collection, model_slug(&embed_model)),
+        (None, None) => format!("{}-reembed", collection),
+    };
+    if store.collection_info(&target).await?.is_some() {
+        anyhow::bail!(
+            "Collection '{}' already exists. Remove it or pick another name with --to.",
+            target
+        );
+    }
+    let backup = format!("{}-backup", collection);
+    if to.is_none() && alias.is_none() && store.collection_info(&backup).await?.is_some() {
+        anyhow::bail!(
+            "Collection '{}' already exists. Remove it or pick another name with --to.",
+            backup
+        );
+    }
+
+    println!(
+        "Re-embedding '{}' with '{}' into '{}'",
+        collection, embed_model, target
+    );
+    let count = rebuild(
+        &*store,
+        backend.as_ref(),
+        collection,
+        &info,
+        &target,
+        metadata,
+    )
+    .await?;
+
+    match (to, alias) {
+        (Some(_), _) => {
+            println!(
+                "\nRe-embedded {} points into '{}'. '{}' is unchanged.",
+                count, target, collection
+            );
+        }
+        (None, Some(alias)) => {
+            store.set_alias(collection, &target).await?;
+            println!(
+                "\nRe-embedded {} points. Alias '{}' now points at '{}'; the old collection '{}' \
+                is kept until you remove it with 'know clean {}'.",
+                count, collection, target, alias.collection, alias.collection
+            );
+        }
+        (None, None) => {
+            // Swap the new collection in under the original name, keeping its aliases.
+            // The name is only free once the original is deleted, so copy it first.
+            let aliases: Vec<_> = store
+                .aliases()
+                .await?
+                .into_iter()
+                .filter(|a| a.collection == collection)
+                .collect();
+            store.copy_collection(collection, &backup).await?;
+            store.delete_collection(collection).await?;
+            store
+                .rename_collection(&target, collection)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to replace '{}'; the original is kept in '{}' and the \
+                        re-embedded copy in '{}'",
+                        collection, backup, target
+                    )
+                })?;
+            for alias in aliases {
+                store.set_alias(&alias.name, collection).await?;
+            }
+            store.delete_collection(&backup).await?;
+            println!("\nRe-embedded {} points in '{}'.", count, collection);
+        }
+    }
+
+    Ok(())
+}
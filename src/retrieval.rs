@@ -0,0 +1,626 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::backend::{parse_list, CollectionBackends, LlmBackend};
+use crate::qdrant::{Condition, Filter, PointKind, ScoredChunk, SearchParams};
+use crate::store::VectorStore;
+
+/// Number of documents to drill into when routing via summaries
+const ROUTED_DOCUMENTS: usize = 3;
+
+/// Over-fetch factor so question hits collapsing onto one chunk still leave `top_k` chunks
+const QUESTION_OVERFETCH: usize = 4;
+
+/// Over-fetch factor giving MMR and the per-source cap candidates to choose from
+const DIVERSITY_OVERFETCH: usize = 4;
+
+/// Rank offset for reciprocal rank fusion; 60 is the value from the original RRF paper
+const RRF_K: f32 = 60.0;
+
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetrievalMode {
+    /// Search document chunks directly
+    #[default]
+    Chunks,
+    /// Find the best matching documents by summary, then search their chunks
+    Summaries,
+}
+
+/// How the user's question is turned into search queries
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryMode {
+    /// Embed the question as-is
+    #[default]
+    Raw,
+    /// Have the generation model rewrite the question into a cleaner search query
+    Rewrite,
+    /// Search the question plus several paraphrases and fuse the results
+    Multi,
+    /// Embed a generated hypothetical answer instead of the question (HyDE)
+    Hyde,
+}
+
+/// Options controlling how chunks are retrieved for a question
+pub struct RetrievalOptions {
+    pub top_k: usize,
+    pub mode: RetrievalMode,
+    pub query_mode: QueryMode,
+    /// Number of paraphrases to generate in multi-query mode
+    pub num_queries: usize,
+    /// Trade-off between relevance (1.0) and diversity (0.0) for MMR; None disables MMR
+    pub mmr_lambda: Option<f32>,
+    /// Maximum number of chunks taken from any one source
+    pub max_per_source: Option<usize>,
+    /// Number of neighbouring chunks to add on each side of every hit
+    pub expand: usize,
+    /// Drop hits whose similarity score is below this
+    pub min_score: Option<f32>,
+    /// Payload conditions every hit must match
+    pub filter: Vec<Condition>,
+}
+
+impl RetrievalOptions {
+    /// Number of candidate chunks to fetch before diversification
+    fn candidates(&self) -> usize {
+        if self.mmr_lambda.is_some() || self.max_per_source.is_some() {
+            self.top_k * DIVERSITY_OVERFETCH
+        } else {
+            self.top_k
+        }
+    }
+
+    /// Add the user's payload conditions to a search filter
+    fn restrict(&self, filter: Filter) -> Filter {
+        self.filter.iter().cloned().fold(filter, Filter::with)
+    }
+}
+
+/// Retrieved chunks along with the search queries that found them
+pub struct Retrieved {
+    pub queries: Vec<String>,
+    pub hits: Vec<ScoredChunk>,
+}
+
+/// Turn a question into the search queries to embed, according to the query mode
+async fn expand_query(
+    backend: &dyn LlmBackend,
+    question: &str,
+    options: &RetrievalOptions,
+) -> Result<Vec<String>> {
+    match options.query_mode {
+        QueryMode::Raw => Ok(vec![question.to_string()]),
+        QueryMode::Rewrite => {
+            let response = backend
+                .chat(
+                    "Rewrite the question you are given as a short, self-contained search \
+                    query using the key terms a document would use. Output only the query.",
+                    question,
+                )
+                .await?
+                .text;
+            let rewritten = parse_list(&response, 1);
+            Ok(if rewritten.is_empty() {
+                vec![question.to_string()]
+            } else {
+                rewritten
+            })
+        }
+        QueryMode::Multi => {
+            let system = format!(
+                "Write {} different paraphrases of the question you are given, using \
+                different wording and synonyms. Output one per line and nothing else.",
+                options.num_queries
+            );
+            let response = backend.chat(&system, question).await?.text;
+            let mut queries = vec![question.to_string()];
+            queries.extend(parse_list(&response, options.num_queries));
+            Ok(queries)
+        }
+        QueryMode::Hyde => {
+            let answer = backend
+                .chat(
+                    "Write a short passage, in the style of internal documentation, that \
+                    would answer the question you are given. It does not need to be \
+                    correct; it is only used for searching. Output only the passage.",
+                    question,
+                )
+                .await?
+                .text;
+            Ok(vec![answer.trim().to_string()])
+        }
+    }
+}
+
+/// Fuse several ranked lists with reciprocal rank fusion.
+///
+/// Hits are ordered by fused rank but keep their best original similarity score.
+fn fuse_rrf(lists: Vec<Vec<ScoredChunk>>) -> Vec<ScoredChunk> {
+    let limit = lists.iter().map(Vec::len).max().unwrap_or(0);
+    let mut fused: HashMap<String, (f32, ScoredChunk)> = HashMap::new();
+
+    for list in lists {
+        for (rank, hit) in list.into_iter().enumerate() {
+            let rrf = 1.0 / (RRF_K + rank as f32 + 1.0);
+            match fused.get_mut(&hit.chunk.id) {
+                Some((total, best)) => {
+                    *total += rrf;
+                    if hit.score > best.score {
+                        *best = hit;
+                    }
+                }
+                None => {
+                    fused.insert(hit.chunk.id.clone(), (rrf, hit));
+                }
+            }
+        }
+    }
+
+    let mut fused: Vec<_> = fused.into_values().collect();
+    fused.sort_by(|a, b| b.0.total_cmp(&a.0));
+    fused.into_iter().take(limit).map(|(_, hit)| hit).collect()
+}
+
+/// Replace question hits with the chunk they point at, keeping each chunk's best score.
+///
+/// A collapsed hit's vector is dropped, since it is the question's rather than the chunk's.
+fn collapse_questions(hits: Vec<ScoredChunk>, limit: usize) -> Vec<ScoredChunk> {
+    let mut seen = HashSet::new();
+    hits.into_iter()
+        .map(|mut hit| {
+            if hit.chunk.kind == PointKind::Question {
+                if let Some(parent_id) = hit.chunk.parent_id.take() {
+                    hit.chunk.id = parent_id;
+                }
+                hit.chunk.kind = PointKind::Chunk;
+                hit.vector = None;
+            }
+            hit
+        })
+        .filter(|hit| seen.insert(hit.chunk.id.clone()))
+        .take(limit)
+        .collect()
+}
+
+/// Search candidate chunks (and questions generated from them) matching a filter
+async fn search_chunks(
+    store: &dyn VectorStore,
+    target: &Target<'_>,
+    query_embedding: Vec<f32>,
+    filter: &Filter,
+    options: &RetrievalOptions,
+) -> Result<Vec<ScoredChunk>> {
+    let limit = options.candidates();
+    let hits = store
+        .search_scored(
+            target.collection,
+            query_embedding,
+            limit * QUESTION_OVERFETCH,
+            filter,
+            options.mmr_lambda.is_some(),
+            &target.search,
+        )
+        .await?;
+    let mut hits = collapse_questions(hits, limit);
+
+    // MMR compares chunks, so hits found through a question need the chunk's own vector
+    if options.mmr_lambda.is_some() {
+        let missing: Vec<String> = hits
+            .iter()
+            .filter(|hit| hit.vector.is_none())
+            .map(|hit| hit.chunk.id.clone())
+            .collect();
+        if !missing.is_empty() {
+            let mut vectors = store.vectors(target.collection, &missing).await?;
+            for hit in hits.iter_mut().filter(|hit| hit.vector.is_none()) {
+                hit.vector = vectors.remove(&hit.chunk.id);
+            }
+        }
+    }
+
+    Ok(hits)
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Pick `top_k` chunks from ranked candidates, applying maximal marginal
+/// relevance and the per-source cap when configured. Summaries pass through.
+fn diversify(hits: Vec<ScoredChunk>, options: &RetrievalOptions) -> Vec<ScoredChunk> {
+    let (summaries, mut candidates): (Vec<_>, Vec<_>) = hits
+        .into_iter()
+        .partition(|hit| hit.chunk.kind == PointKind::Summary);
+
+    let mut selected: Vec<ScoredChunk> = Vec::new();
+    let mut per_source: HashMap<String, usize> = HashMap::new();
+
+    while selected.len() < options.top_k {
+        let eligible = candidates.iter().enumerate().filter(|(_, hit)| {
+            options
+                .max_per_source
+                .is_none_or(|cap| per_source.get(&hit.chunk.source).copied().unwrap_or(0) < cap)
+        });
+
+        let best = match options.mmr_lambda {
+            // Candidates are already in relevance order
+            None => eligible.map(|(i, _)| i).next(),
+            Some(lambda) => eligible
+                .map(|(i, hit)| {
+                    let redundancy = selected
+                        .iter()
+                        .filter_map(|s| {
+                            Some(cosine_similarity(hit.vector.as_ref()?, s.vector.as_ref()?))
+                        })
+                        .fold(0.0, f32::max);
+                    (i, lambda * hit.score - (1.0 - lambda) * redundancy)
+                })
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(i, _)| i),
+        };
+
+        let Some(best) = best else {
+            break;
+        };
+        let hit = candidates.remove(best);
+        *per_source.entry(hit.chunk.source.clone()).or_default() += 1;
+        selected.push(hit);
+    }
+
+    summaries.into_iter().chain(selected).collect()
+}
+
+/// A run of positions in one document covering one or more hits
+struct Span {
+    doc_id: String,
+    start: usize,
+    end: usize,
+    /// Position of the first contributing hit in the result list
+    order: usize,
+    best: ScoredChunk,
+}
+
+/// Expand chunk hits with `window` neighbouring chunks on each side, merging
+/// overlapping windows from the same document into a single passage
+async fn expand_neighbours(
+    store: &dyn VectorStore,
+    collection: &str,
+    hits: Vec<ScoredChunk>,
+    window: usize,
+) -> Result<Vec<ScoredChunk>> {
+    if window == 0 {
+        return Ok(hits);
+    }
+
+    // Summaries and chunks ingested before positions were recorded pass through
+    let mut passthrough: Vec<(usize, ScoredChunk)> = Vec::new();
+    let mut windows: Vec<Span> = Vec::new();
+    for (order, hit) in hits.into_iter().enumerate() {
+        match (hit.chunk.kind, hit.chunk.doc_id.clone(), hit.chunk.position) {
+            (PointKind::Chunk, Some(doc_id), Some(position)) => windows.push(Span {
+                doc_id,
+                start: position.saturating_sub(window),
+                end: position + window,
+                order,
+                best: hit,
+            }),
+            _ => passthrough.push((order, hit)),
+        }
+    }
+
+    windows.sort_by(|a, b| a.doc_id.cmp(&b.doc_id).then(a.start.cmp(&b.start)));
+
+    let mut spans: Vec<Span> = Vec::new();
+    for span in windows {
+        match spans.last_mut() {
+            Some(last) if last.doc_id == span.doc_id && span.start <= last.end + 1 => {
+                last.end = last.end.max(span.end);
+                last.order = last.order.min(span.order);
+                if span.best.score > last.best.score {
+                    last.best = span.best;
+                }
+            }
+            _ => spans.push(span),
+        }
+    }
+
+    let fetches = spans.iter().map(|span| async move {
+        let filter = Filter::chunks()
+            .with(Condition::matches("doc_id", span.doc_id.as_str()))
+            .with(Condition::range(
+                "position",
+                span.start as f64,
+                span.end as f64,
+            ));
+        store.scroll(collection, &filter).await
+    });
+    let neighbours = futures::future::try_join_all(fetches).await?;
+
+    let mut expanded = passthrough;
+    for (mut span, mut chunks) in spans.into_iter().zip(neighbours) {
+        chunks.sort_by_key(|c| c.position);
+        if !chunks.is_empty() {
+            span.best.chunk.content = chunks
+                .iter()
+                .map(|c| c.content.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        expanded.push((span.order, span.best));
+    }
+
+    expanded.sort_by_key(|(order, _)| *order);
+    Ok(expanded.into_iter().map(|(_, hit)| hit).collect())
+}
+
+/// A collection to search, with the backend that embeds queries for it
+pub struct Target<'a> {
+    pub collection: &'a str,
+    pub backend: &'a dyn LlmBackend,
+    pub search: SearchParams,
+}
+
+impl<'a> Target<'a> {
+    /// Targets for `collections`, each embedding with the model it was built with
+    /// and searching with its own parameters; every call checks the collections
+    /// against their metadata again
+    pub async fn all(
+        backends: &'a CollectionBackends,
+        store: &dyn VectorStore,
+        collections: &'a [String],
+    ) -> Result<Vec<Self>> {
+        let mut targets = Vec::with_capacity(collections.len());
+        for collection in collections {
+            let (backend, search) = backends.recheck(store, collection).await?;
+            targets.push(Target {
+                collection,
+                backend,
+                search,
+            });
+        }
+        Ok(targets)
+    }
+}
+
+/// Retrieve the chunks (and, when routing, summaries) most relevant to a question
+/// from one or more collections; `backend` generates the search queries
+pub async fn retrieve(
+    backend: &dyn LlmBackend,
+    store: &dyn VectorStore,
+    targets: &[Target<'_>],
+    question: &str,
+    options: &RetrievalOptions,
+) -> Result<Retrieved> {
+    let queries = expand_query(backend, question, options).await?;
+
+    let searches = targets
+        .iter()
+        .map(|target| search_collection(store, target, &queries, options));
+    let mut results = futures::future::try_join_all(searches).await?;
+
+    let hits = if results.len() == 1 {
+        results.remove(0)
+    } else {
+        fuse_collections(targets, results, options.top_k)
+    };
+
+    Ok(Retrieved { queries, hits })
+}
+
+/// Search one collection with every query
+async fn search_collection(
+    store: &dyn VectorStore,
+    target: &Target<'_>,
+    queries: &[String],
+    options: &RetrievalOptions,
+) -> Result<Vec<ScoredChunk>> {
+    let searches = queries
+        .iter()
+        .map(|query| search_query(store, target, query, options));
+    let mut lists = futures::future::try_join_all(searches).await?;
+
+    let hits = if lists.len() == 1 {
+        lists.remove(0)
+    } else {
+        fuse_rrf(lists)
+    };
+    let hits = match options.min_score {
+        Some(min_score) => hits.into_iter().filter(|hit| hit.score >= min_score).collect(),
+        None => hits,
+    };
+    let hits = diversify(hits, options);
+    expand_neighbours(store, target.collection, hits, options.expand).await
+}
+
+/// Merge the hits from several collections into one ranking with reciprocal
+/// rank fusion, labelling each hit with its collection.
+///
+/// Similarity scores from different collections aren't comparable, even with
+/// the same model, so hits are ranked by their rank within their collection
+/// and keep their raw score. `min_score` has already been applied to each list.
+fn fuse_collections(
+    targets: &[Target<'_>],
+    results: Vec<Vec<ScoredChunk>>,
+    top_k: usize,
+) -> Vec<ScoredChunk> {
+    let mut hits = Vec::new();
+    for (target, list) in targets.iter().zip(results) {
+        for (rank, mut hit) in list.into_iter().enumerate() {
+            hit.collection = Some(target.collection.to_string());
+            hit.fused_score = Some(1.0 / (RRF_K + rank as f32 + 1.0));
+            hits.push(hit);
+        }
+    }
+
+    // Stable, so hits of equal rank keep the order the collections were given in
+    let fused = |hit: &ScoredChunk| hit.fused_score.unwrap_or_default();
+    hits.sort_by(|a, b| fused(b).total_cmp(&fused(a)));
+    hits.truncate(top_k);
+    hits
+}
+
+/// Run a single search query
+async fn search_query(
+    store: &dyn VectorStore,
+    target: &Target<'_>,
+    query: &str,
+    options: &RetrievalOptions,
+) -> Result<Vec<ScoredChunk>> {
+    let query_embedding = target.backend.embed(query).await?;
+
+    match options.mode {
+        RetrievalMode::Chunks => {
+            search_chunks(
+                store,
+                target,
+                query_embedding,
+                &options.restrict(Filter::chunks_and_questions()),
+                options,
+            )
+            .await
+        }
+        RetrievalMode::Summaries => {
+            let summaries = store
+                .search_scored(
+                    target.collection,
+                    query_embedding.clone(),
+                    ROUTED_DOCUMENTS,
+                    &options.restrict(Filter::kind(PointKind::Summary)),
+                    false,
+                    &target.search,
+                )
+                .await?;
+
+            // Collections ingested without --summarize have nothing to route by
+            if summaries.is_empty() {
+                return search_chunks(
+                    store,
+                    target,
+                    query_embedding,
+                    &options.restrict(Filter::chunks_and_questions()),
+                    options,
+                )
+                .await;
+            }
+
+            // Drill into the document each summary was written for by its ID:
+            // a source may have been ingested more than once, or share chunks
+            // with another copy
+            let mut chunks = Vec::new();
+            for summary in &summaries {
+                let Some(doc_id) = &summary.chunk.doc_id else {
+                    continue;
+                };
+                let filter = options.restrict(
+                    Filter::chunks_and_questions()
+                        .with(Condition::matches("doc_id", doc_id.as_str())),
+                );
+                chunks.extend(
+                    search_chunks(
+                        store,
+                        target,
+                        query_embedding.clone(),
+                        &filter,
+                        options,
+                    )
+                    .await?,
+                );
+            }
+
+            chunks.sort_by(|a, b| b.score.total_cmp(&a.score));
+            chunks.truncate(options.candidates());
+
+            Ok(summaries.into_iter().chain(chunks).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qdrant::DocumentChunk;
+
+    fn hit(id: &str, source: &str, score: f32, vector: [f32; 2]) -> ScoredChunk {
+        ScoredChunk {
+            chunk: DocumentChunk::from_payload(
+                id.to_string(),
+                serde_json::json!({ "content": format!("text of {}", id), "source": source }),
+            )
+            .unwrap(),
+            score,
+            vector: Some(vector.to_vec()),
+            collection: None,
+            fused_score: None,
+        }
+    }
+
+    fn options(
+        top_k: usize,
+        mmr_lambda: Option<f32>,
+        max_per_source: Option<usize>,
+    ) -> RetrievalOptions {
+        RetrievalOptions {
+            top_k,
+            mode: RetrievalMode::Chunks,
+            query_mode: QueryMode::Raw,
+            num_queries: 0,
+            mmr_lambda,
+            max_per_source,
+            expand: 0,
+            min_score: None,
+            filter: Vec::new(),
+        }
+    }
+
+    fn ids(hits: &[ScoredChunk]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.chunk.id.as_str()).collect()
+    }
+
+    /// Two near-identical chunks followed by a less relevant but different one
+    fn candidates() -> Vec<ScoredChunk> {
+        vec![
+            hit("a", "one.md", 0.9, [1.0, 0.0]),
+            hit("a-copy", "two.md", 0.89, [0.99, 0.01]),
+            hit("b", "three.md", 0.7, [0.0, 1.0]),
+        ]
+    }
+
+    #[test]
+    fn full_relevance_keeps_the_ranking() {
+        let kept = diversify(candidates(), &options(2, Some(1.0), None));
+        assert_eq!(ids(&kept), ["a", "a-copy"]);
+    }
+
+    #[test]
+    fn lower_lambda_skips_near_duplicates() {
+        let kept = diversify(candidates(), &options(2, Some(0.5), None));
+        assert_eq!(ids(&kept), ["a", "b"]);
+    }
+
+    #[test]
+    fn sources_are_capped() {
+        let hits = vec![
+            hit("x1", "x.md", 0.9, [1.0, 0.0]),
+            hit("x2", "x.md", 0.8, [0.0, 1.0]),
+            hit("x3", "x.md", 0.7, [1.0, 1.0]),
+            hit("y1", "y.md", 0.6, [1.0, 0.0]),
+        ];
+        let kept = diversify(hits.clone(), &options(3, None, Some(2)));
+        assert_eq!(ids(&kept), ["x1", "x2", "y1"]);
+
+        let kept = diversify(hits, &options(4, Some(1.0), Some(1)));
+        assert_eq!(ids(&kept), ["x1", "y1"]);
+    }
+}
@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::io::IsTerminal;
+
+use crate::backend::CollectionBackends;
+use crate::cli::{Cli, RetrievalArgs};
+use crate::config::Config;
+use crate::qdrant::{PointKind, ScoredChunk};
+use crate::retrieval::{retrieve, QueryMode, Target};
+use crate::store;
+
+/// Number of words shown around the best matching part of a chunk
+const SNIPPET_WORDS: usize = 40;
+
+/// Common words that would otherwise be highlighted everywhere
+const STOPWORDS: &[&str] = &[
+    "about", "and", "are", "can", "does", "for", "from", "how", "into", "that", "the", "their",
+    "there", "this", "was", "what", "when", "where", "which", "who", "why", "with", "you", "your",
+];
+
+/// Lowercased query words worth highlighting
+fn query_terms(query: &str) -> HashSet<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3)
+        .map(|w| w.to_lowercase())
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// Byte ranges of the alphanumeric words in `text`
+fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    spans
+}
+
+/// The window of a chunk with the most query terms, with those terms
+/// marked up by `highlight` and whitespace collapsed onto one line
+fn snippet(content: &str, terms: &HashSet<String>, highlight: impl Fn(&str) -> String) -> String {
+    let spans = word_spans(content);
+    if spans.is_empty() {
+        return String::new();
+    }
+
+    let is_match: Vec<bool> = spans
+        .iter()
+        .map(|&(s, e)| terms.contains(&content[s..e].to_lowercase()))
+        .collect();
+
+    // Slide a window over the words and keep the one with the most matches
+    let width = SNIPPET_WORDS.min(spans.len());
+    let mut count = is_match[..width].iter().filter(|&&m| m).count();
+    let (mut best, mut best_count) = (0, count);
+    for start in 1..=spans.len() - width {
+        count = count + is_match[start + width - 1] as usize - is_match[start - 1] as usize;
+        if count > best_count {
+            best = start;
+            best_count = count;
+        }
+    }
+
+    // Centre the window on the matches it contains
+    let matched: Vec<usize> = (best..best + width).filter(|&i| is_match[i]).collect();
+    if let (Some(&first), Some(&last)) = (matched.first(), matched.last()) {
+        best = ((first + last) / 2)
+            .saturating_sub(width / 2)
+            .min(spans.len() - width);
+    }
+    let end = best + width;
+
+    let mut out = String::new();
+    if best > 0 {
+        out.push_str("... ");
+    }
+    let mut last = spans[best].0;
+    for (&(s, e), &matched) in spans[best..end].iter().zip(&is_match[best..end]) {
+        out.push_str(&content[last..s]);
+        if matched {
+            out.push_str(&highlight(&content[s..e]));
+        } else {
+            out.push_str(&content[s..e]);
+        }
+        last = e;
+    }
+    if end < spans.len() {
+        out.push_str(" ...");
+    }
+
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Where in its document a hit comes from, for display
+pub fn location(hit: &ScoredChunk) -> String {
+    let chunk = &hit.chunk;
+    let mut parts = Vec::new();
+    if chunk.kind == PointKind::Summary {
+        parts.push("summary".to_string());
+    }
+    if let Some(page) = chunk.page {
+        parts.push(format!("page {}", page));
+    }
+    if let Some(position) = chunk.position {
+        parts.push(format!("chunk {}", position + 1));
+    }
+
+    let mut location = match &hit.collection {
+        Some(collection) => format!("[{}] {}", collection, chunk.source),
+        None => chunk.source.clone(),
+    };
+    if let Some(heading) = &chunk.heading {
+        location.push_str(&format!(" > {}", heading));
+    }
+    if !parts.is_empty() {
+        location.push_str(&format!(" ({})", parts.join(", ")));
+    }
+    location
+}
+
+/// Search the knowledge base and print the matching chunks without generating an answer
+pub async fn search(
+    cli: &Cli,
+    query: &str,
+    top_k: usize,
+    json: bool,
+    retrieval: &RetrievalArgs,
+) -> Result<()> {
+    let store = store::create_store(cli).await?;
+
+    // Check if collection has data
+    if store.points_count(&cli.collection).await? == 0 {
+        anyhow::bail!("Knowledge base is empty. Run 'know ingest <path>' first.");
+    }
+
+    // One backend per embedding model used by the collections
+    let config = Config::load(cli.config.as_deref())?;
+    let backends = CollectionBackends::new(
+        cli,
+        &config,
+        &*store,
+        cli.collection.iter().map(String::as_str),
+    )
+    .await?;
+
+    let options = retrieval.options(top_k);
+    let targets = Target::all(&backends, &*store, &cli.collection).await?;
+    let retrieved = retrieve(backends.default(), &*store, &targets, query, &options).await?;
+
+    if cli.verbose && options.query_mode != QueryMode::Raw {
+        eprintln!("Search queries:");
+        for query in &retrieved.queries {
+            eprintln!("  - {}", query);
+        }
+        eprintln!();
+    }
+
+    let terms = query_terms(query);
+
+    if json {
+        let hits: Vec<serde_json::Value> = retrieved
+            .hits
+            .iter()
+            .enumerate()
+            .map(|(i, hit)| {
+                serde_json::json!({
+                    "rank": i + 1,
+                    "score": hit.score,
+                    "fused_score": hit.fused_score,
+                    "id": hit.chunk.id,
+                    "collection": hit.collection,
+                    "source": hit.chunk.source,
+                    "other_sources": hit.chunk.other_sources,
+                    "kind": hit.chunk.kind,
+                    "question": hit.chunk.question,
+                    "doc_id": hit.chunk.doc_id,
+                    "position": hit.chunk.position,
+                    "heading": hit.chunk.heading,
+                    "page": hit.chunk.page,
+                    "snippet": snippet(&hit.chunk.content, &terms, str::to_string),
+                    "content": hit.chunk.content,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&hits)?);
+        return Ok(());
+    }
+
+    if retrieved.hits.is_empty() {
+        println!("No matching chunks found.");
+        return Ok(());
+    }
+
+    // Only use escape codes when they will be rendered
+    let color = std::io::stdout().is_terminal();
+    let highlight = |word: &str| {
+        if color {
+            format!("\x1b[1;33m{}\x1b[0m", word)
+        } else {
+            word.to_string()
+        }
+    };
+
+    for (i, hit) in retrieved.hits.iter().enumerate() {
+        println!("{:>2}. [{:.3}] {}", i + 1, hit.score, location(hit));
+        if let Some(question) = &hit.chunk.question {
+            println!("    matched question: {}", question);
+        }
+        println!("    {}", snippet(&hit.chunk.content, &terms, highlight));
+        for source in &hit.chunk.other_sources {
+            println!("    also in: {}", source);
+        }
+        println!();
+    }
+
+    Ok(())
+}
@@ -12,20 +12,31 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::backend::{create_backend, LlmBackend};
+use crate::backend::{CollectionBackends, TokenUsage};
+use crate::citations::{resolve, Citation};
 use crate::cli::Cli;
-use crate::qdrant::QdrantClient;
+use crate::config::Config;
+use crate::context::ContextBuilder;
+use crate::grounding::{verify, Grounding, VerifyMode};
+use crate::prompt::{PromptTemplate, PromptVars};
+use crate::retrieval::{retrieve, QueryMode, RetrievalMode, RetrievalOptions, Target};
+use crate::store::{self, VectorStore};
 
 struct AppState {
-    backend: Box<dyn LlmBackend>,
-    qdrant: QdrantClient,
-    collection: String,
+    backends: CollectionBackends,
+    store: Box<dyn VectorStore>,
+    /// Collections searched when a request doesn't name any
+    collections: Vec<String>,
+    context_builder: ContextBuilder,
+    template: PromptTemplate,
+    min_score: Option<f32>,
+    not_found_message: String,
 }
 
 // OpenAI-compatible request/response types
 #[derive(Deserialize)]
 struct ChatCompletionRequest {
-    #[allow(dead_code)]
+    /// Comma-separated collections to search; none means the defaults
     model: Option<String>,
     messages: Vec<ChatMessage>,
     #[serde(default)]
@@ -33,12 +44,34 @@ struct ChatCompletionRequest {
     stream: bool,
     #[serde(default = "default_top_k")]
     top_k: usize,
+    #[serde(default)]
+    retrieval: RetrievalMode,
+    #[serde(default)]
+    query_mode: QueryMode,
+    #[serde(default = "default_num_queries")]
+    num_queries: usize,
+    #[serde(default)]
+    mmr_lambda: Option<f32>,
+    #[serde(default)]
+    max_per_source: Option<usize>,
+    #[serde(default)]
+    expand: usize,
+    #[serde(default)]
+    min_score: Option<f32>,
+    #[serde(default)]
+    verify: Option<VerifyMode>,
+    #[serde(default)]
+    verify_threshold: Option<f32>,
 }
 
 fn default_top_k() -> usize {
     5
 }
 
+fn default_num_queries() -> usize {
+    3
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 struct ChatMessage {
     role: String,
@@ -53,6 +86,11 @@ struct ChatCompletionResponse {
     model: String,
     choices: Vec<ChatChoice>,
     usage: Usage,
+    /// Knowledge base chunks cited in the answer by their bracketed number
+    citations: Vec<Citation>,
+    /// Per-statement support check, when the request sets `verify`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grounding: Option<Grounding>,
 }
 
 #[derive(Serialize)]
@@ -69,35 +107,78 @@ struct Usage {
     total_tokens: usize,
 }
 
-
 #[derive(Serialize)]
 struct HealthResponse {
     status: String,
     backend: String,
-    collection: String,
+    collections: Vec<String>,
 }
 
 async fn health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     Json(HealthResponse {
         status: "ok".to_string(),
-        backend: state.backend.name().to_string(),
-        collection: state.collection.clone(),
+        backend: state.backends.default().name().to_string(),
+        collections: state.collections.clone(),
     })
 }
 
+/// Collections named by a request's `model` field as a comma list, or None if
+/// it names none and the defaults are searched. Fails with the status to
+/// answer if any of them doesn't exist or can't be looked up.
+async fn requested_collections(
+    store: &dyn VectorStore,
+    model: Option<&str>,
+) -> Result<Option<Vec<String>>, (StatusCode, String)> {
+    let names: Vec<String> = model
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.is_empty() {
+        return Ok(None);
+    }
+
+    let mut missing = Vec::new();
+    for name in &names {
+        match store.collection_info(name).await {
+            Ok(Some(_)) => {}
+            Ok(None) => missing.push(name.as_str()),
+            Err(e) => {
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to look up collection '{}': {}", name, e),
+                ))
+            }
+        }
+    }
+    if !missing.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Collection '{}' not found", missing.join("', '")),
+        ));
+    }
+    Ok(Some(names))
+}
+
 async fn chat_completions(
     State(state): State<Arc<AppState>>,
     Json(request): Json<ChatCompletionRequest>,
 ) -> impl IntoResponse {
     // Extract the user's question from the last message
-    let user_message = request
-        .messages
-        .iter()
-        .rev()
-        .find(|m| m.role == "user")
-        .map(|m| m.content.clone())
+    let last_user = request.messages.iter().rposition(|m| m.role == "user");
+    let user_message = last_user
+        .map(|i| request.messages[i].content.clone())
         .unwrap_or_default();
 
+    // Earlier turns are available to prompt templates as {{history}}
+    let history = request.messages[..last_user.unwrap_or(0)]
+        .iter()
+        .filter(|m| m.role != "system")
+        .map(|m| format!("{}: {}", m.role, m.content))
+        .collect::<Vec<_>>()
+        .join("\n");
+
     if user_message.is_empty() {
         return (
             StatusCode::BAD_REQUEST,
@@ -111,28 +192,76 @@ async fn chat_completions(
             .into_response();
     }
 
-    // Embed the question
-    let query_embedding = match state.backend.embed(&user_message).await {
-        Ok(e) => e,
+    if let Some(lambda) = request.mmr_lambda.filter(|l| !(0.0..=1.0).contains(l)) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": {
+                    "message": format!("mmr_lambda must be between 0.0 and 1.0, got {}", lambda),
+                    "type": "invalid_request_error"
+                }
+            })),
+        )
+            .into_response();
+    }
+
+    // Search for relevant chunks
+    let options = RetrievalOptions {
+        top_k: request.top_k,
+        mode: request.retrieval,
+        query_mode: request.query_mode,
+        num_queries: request.num_queries,
+        mmr_lambda: request.mmr_lambda,
+        max_per_source: request.max_per_source,
+        expand: request.expand,
+        min_score: request.min_score.or(state.min_score),
+        filter: Vec::new(),
+    };
+    let requested = requested_collections(&*state.store, request.model.as_deref()).await;
+    let collections = match requested {
+        Ok(Some(collections)) => collections,
+        Ok(None) => state.collections.clone(),
+        Err((status, message)) => {
+            let kind = if status == StatusCode::BAD_REQUEST {
+                "invalid_request_error"
+            } else {
+                "server_error"
+            };
+            return (
+                status,
+                Json(serde_json::json!({
+                    "error": {
+                        "message": message,
+                        "type": kind
+                    }
+                })),
+            )
+                .into_response();
+        }
+    };
+    let targets = match Target::all(&state.backends, &*state.store, &collections).await {
+        Ok(targets) => targets,
         Err(e) => {
             return (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_REQUEST,
                 Json(serde_json::json!({
                     "error": {
-                        "message": format!("Failed to embed query: {}", e),
-                        "type": "server_error"
+                        "message": e.to_string(),
+                        "type": "invalid_request_error"
                     }
                 })),
             )
                 .into_response();
         }
     };
-
-    // Search for relevant chunks
-    let results = match state
-        .qdrant
-        .search(&state.collection, query_embedding, request.top_k)
-        .await
+    let retrieved = match retrieve(
+        state.backends.default(),
+        &*state.store,
+        &targets,
+        &user_message,
+        &options,
+    )
+    .await
     {
         Ok(r) => r,
         Err(e) => {
@@ -149,15 +278,23 @@ async fn chat_completions(
         }
     };
 
-    // Build context
-    let context: String = results
-        .iter()
-        .map(|chunk| format!("[Source: {}]\n{}", chunk.source, chunk.content))
-        .collect::<Vec<_>>()
-        .join("\n---\n");
+    // Nothing relevant enough: answer without calling the model
+    if retrieved.hits.is_empty() {
+        return Json(completion(state.not_found_message.clone(), None, Vec::new(), None))
+            .into_response();
+    }
+
+    // Build context, fitted to the model's context window
+    let context = state.context_builder.build(&user_message, retrieved.hits);
 
     // Generate response
-    let response = match state.backend.generate(&user_message, &context).await {
+    let system = state.template.render(&PromptVars {
+        context: &context.text,
+        question: &user_message,
+        collection: &collections.join(", "),
+        history: &history,
+    });
+    let response = match state.backends.default().chat(&system, &user_message).await {
         Ok(r) => r,
         Err(e) => {
             return (
@@ -173,8 +310,59 @@ async fn chat_completions(
         }
     };
 
-    // Build OpenAI-compatible response
-    let completion = ChatCompletionResponse {
+    // Check the model only cited blocks it was actually given
+    let cited = resolve(&response.text, &context.hits);
+
+    // Optionally check each statement against the chunks it was generated from
+    let grounding = match request.verify {
+        Some(mode) => match verify(
+            state.backends.default(),
+            &cited.text,
+            &context,
+            mode,
+            request.verify_threshold,
+        )
+        .await
+        {
+            Ok(grounding) => Some(grounding),
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({
+                        "error": {
+                            "message": format!("Failed to verify response: {}", e),
+                            "type": "server_error"
+                        }
+                    })),
+                )
+                    .into_response();
+            }
+        },
+        None => None,
+    };
+
+    Json(completion(
+        cited.text,
+        response.usage,
+        cited.citations,
+        grounding,
+    ))
+    .into_response()
+}
+
+/// Build an OpenAI-compatible response
+fn completion(
+    content: String,
+    usage: Option<TokenUsage>,
+    citations: Vec<Citation>,
+    grounding: Option<Grounding>,
+) -> ChatCompletionResponse {
+    let usage = usage.unwrap_or(TokenUsage {
+        prompt_tokens: 0,
+        completion_tokens: 0,
+    });
+
+    ChatCompletionResponse {
         id: format!("chatcmpl-{}", uuid::Uuid::new_v4()),
         object: "chat.completion".to_string(),
         created: std::time::SystemTime::now()
@@ -186,40 +374,62 @@ async fn chat_completions(
             index: 0,
             message: ChatMessage {
                 role: "assistant".to_string(),
-                content: response,
+                content,
             },
             finish_reason: "stop".to_string(),
         }],
         usage: Usage {
-            prompt_tokens: 0,
-            completion_tokens: 0,
-            total_tokens: 0,
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.prompt_tokens + usage.completion_tokens,
         },
-    };
-
-    Json(completion).into_response()
+        citations,
+        grounding,
+    }
 }
 
 /// Serve an OpenAI-compatible API endpoint
-pub async fn serve(cli: &Cli, port: u16) -> Result<()> {
-    // Check if qdrant is available
-    let qdrant = QdrantClient::new(Some(&cli.qdrant_url));
-    if !qdrant.is_available().await {
-        anyhow::bail!(
-            "Qdrant is not available at {}. Run 'know up' to start services.",
-            cli.qdrant_url
-        );
+pub async fn serve(cli: &Cli, port: u16, min_score: Option<f32>) -> Result<()> {
+    // Check the store is available
+    let store = store::create_store(cli).await?;
+    if !store.is_available().await {
+        anyhow::bail!(store::unavailable_message(cli));
     }
 
-    // Create backend
-    let backend = create_backend(cli).await?;
+    // Prompt template: --prompt, the config, or the one stored with the collection
+    let config = Config::load(cli.config.as_deref())?;
+    let primary = &cli.collection[0];
+    let stored = store.get_metadata(primary).await?;
+    let template = PromptTemplate::resolve(
+        cli.prompt.as_deref(),
+        &config,
+        primary,
+        stored.as_ref().and_then(|m| m.prompt.as_deref()),
+    )?;
+
+    // Create backends for the default collections and any others a request may
+    // name; each request checks its collections against their metadata again
+    let existing = store.list_collections().await?;
+    let collections = cli.collection.iter().chain(&existing).map(String::as_str);
+    let backends = CollectionBackends::new(cli, &config, &*store, collections).await?;
+
+    // Fail now rather than on every request if the defaults can't be queried
+    Target::all(&backends, &*store, &cli.collection).await?;
+
+    println!("Using backend: {}", backends.default().name());
 
-    println!("Using backend: {}", backend.name());
+    let context_builder =
+        ContextBuilder::new(backends.default(), cli.context_length, cli.answer_tokens).await;
+    println!("Context length: {} tokens", context_builder.context_length());
 
     let state = Arc::new(AppState {
-        backend,
-        qdrant,
-        collection: cli.collection.clone(),
+        backends,
+        store,
+        collections: cli.collection.clone(),
+        context_builder,
+        template,
+        min_score,
+        not_found_message: cli.not_found_message.clone(),
     });
 
     let cors = CorsLayer::new()
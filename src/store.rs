@@ -0,0 +1,257 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::cli::Cli;
+use crate::docker;
+use crate::local::LocalStore;
+#[cfg(feature = "postgres")]
+use crate::postgres::PostgresStore;
+use crate::qdrant::{
+    Alias, CollectionInfo, CollectionMetadata, DocumentChunk, Filter, QdrantClient, ScoredChunk,
+    SearchParams, StorageSettings,
+};
+
+/// Where collections are kept
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq)]
+pub enum StoreType {
+    /// Qdrant, started with Docker Compose when it isn't running
+    #[default]
+    Qdrant,
+    /// Files under --store-path, searched in process without any services
+    Local,
+    /// PostgreSQL with pgvector at --store-url (needs the postgres feature)
+    Postgres,
+}
+
+/// Storage and similarity search of embedded document chunks
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    /// Whether the store can be reached
+    async fn is_available(&self) -> bool;
+
+    /// Ensure a collection exists with the right vector size, creating it
+    /// with `settings` if it doesn't
+    async fn ensure_collection(
+        &self,
+        collection: &str,
+        vector_size: usize,
+        settings: &StorageSettings,
+    ) -> Result<()>;
+
+    /// Create a collection configured like an existing one, but holding
+    /// `vector_size`-dimensional vectors
+    async fn create_collection_like(
+        &self,
+        collection: &str,
+        like: &CollectionInfo,
+        vector_size: usize,
+    ) -> Result<()>;
+
+    /// Store chunks with their embeddings, replacing points with the same IDs
+    async fn upsert_batch(
+        &self,
+        collection: &str,
+        chunks: &[DocumentChunk],
+        embeddings: Vec<Vec<f32>>,
+    ) -> Result<()>;
+
+    /// Search for similar points matching a filter, keeping IDs and similarity scores
+    async fn search_scored(
+        &self,
+        collection: &str,
+        query_embedding: Vec<f32>,
+        limit: usize,
+        filter: &Filter,
+        with_vector: bool,
+        params: &SearchParams,
+    ) -> Result<Vec<ScoredChunk>>;
+
+    /// Fetch every point matching a filter
+    async fn scroll(&self, collection: &str, filter: &Filter) -> Result<Vec<DocumentChunk>>;
+
+    /// Payloads of the points matching a filter, with only the given fields
+    async fn scroll_fields(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        fields: &[&str],
+    ) -> Result<Vec<serde_json::Value>>;
+
+    /// Vectors of the points with these IDs; IDs that don't exist are left out
+    async fn vectors(&self, collection: &str, ids: &[String]) -> Result<HashMap<String, Vec<f32>>>;
+
+    /// Record the source a chunk is stored under and the other sources it was found in
+    async fn set_sources(
+        &self,
+        collection: &str,
+        id: &str,
+        source: &str,
+        other_sources: &[String],
+    ) -> Result<()>;
+
+    /// Delete every point matching a filter
+    async fn delete_points(&self, collection: &str, filter: &Filter) -> Result<()>;
+
+    /// Size and configuration of a collection, or None if it doesn't exist
+    async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>>;
+
+    /// Names of all collections, sorted
+    async fn list_collections(&self) -> Result<Vec<String>>;
+
+    /// Copy every point of a collection, metadata included, into a new
+    /// collection with the same configuration; returns the number of points
+    async fn copy_collection(&self, from: &str, to: &str) -> Result<usize>;
+
+    /// Delete a collection
+    async fn delete_collection(&self, collection: &str) -> Result<()>;
+
+    /// Read the metadata record of a collection, if there is one
+    async fn get_metadata(&self, collection: &str) -> Result<Option<CollectionMetadata>>;
+
+    /// Store the metadata record of an existing collection, replacing any previous one
+    async fn set_metadata(&self, collection: &str, metadata: &CollectionMetadata) -> Result<()>;
+
+    /// All aliases, sorted by alias name
+    async fn aliases(&self) -> Result<Vec<Alias>> {
+        Ok(Vec::new())
+    }
+
+    /// Point an alias at a collection, replacing whatever it pointed at before
+    async fn set_alias(&self, _alias: &str, _collection: &str) -> Result<()> {
+        anyhow::bail!("Aliases are only supported with --store qdrant")
+    }
+
+    /// Remove an alias; the collection it points at is kept
+    async fn delete_alias(&self, _alias: &str) -> Result<()> {
+        anyhow::bail!("Aliases are only supported with --store qdrant")
+    }
+
+    /// Rename a collection by copying it under the new name, moving its
+    /// aliases over and deleting the original; returns the number of points
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<usize> {
+        let copied = self.copy_collection(from, to).await?;
+
+        for alias in self.aliases().await? {
+            if alias.collection == from {
+                self.set_alias(&alias.name, to).await?;
+            }
+        }
+
+        self.delete_collection(from).await?;
+        Ok(copied)
+    }
+
+    /// Number of distinct sources with chunks in a collection
+    async fn count_sources(&self, collection: &str) -> Result<usize> {
+        let payloads = self
+            .scroll_fields(collection, &Filter::chunks(), &["source"])
+            .await?;
+        let sources: std::collections::HashSet<&str> = payloads
+            .iter()
+            .filter_map(|payload| payload.get("source").and_then(|s| s.as_str()))
+            .collect();
+        Ok(sources.len())
+    }
+
+    /// Total number of points in the given collections. A missing collection
+    /// counts as empty on its own, but is an error when searching several.
+    async fn points_count(&self, collections: &[String]) -> Result<usize> {
+        let mut total = 0;
+        for collection in collections {
+            match self.collection_info(collection).await? {
+                Some(info) => total += info.points_count,
+                None if collections.len() > 1 => {
+                    anyhow::bail!("Collection '{}' not found", collection)
+                }
+                None => {}
+            }
+        }
+        Ok(total)
+    }
+}
+
+/// Open the store selected on the command line
+pub async fn create_store(cli: &Cli) -> Result<Box<dyn VectorStore>> {
+    match cli.store_type() {
+        StoreType::Qdrant => Ok(Box::new(QdrantClient::from_cli(cli)?)),
+        StoreType::Local => Ok(Box::new(LocalStore::open(store_path(cli)?)?)),
+        #[cfg(feature = "postgres")]
+        StoreType::Postgres => Ok(Box::new(PostgresStore::connect(cli).await?)),
+        #[cfg(not(feature = "postgres"))]
+        StoreType::Postgres => anyhow::bail!(
+            "This build of know has no PostgreSQL support. Rebuild it with --features postgres, \
+            or use another --store."
+        ),
+    }
+}
+
+/// Directory of the local store: --store-path, else `~/.local/share/know` on Linux
+fn store_path(cli: &Cli) -> Result<PathBuf> {
+    match &cli.store_path {
+        Some(path) => Ok(path.clone()),
+        None => dirs::data_dir()
+            .map(|dir| dir.join("know"))
+            .context("No data directory to keep the local store in; set --store-path"),
+    }
+}
+
+/// Start the services the selected store needs
+pub async fn ensure_running(cli: &Cli) -> Result<()> {
+    match cli.store_type() {
+        StoreType::Qdrant => docker::ensure_running().await,
+        StoreType::Local | StoreType::Postgres => Ok(()),
+    }
+}
+
+/// Why the selected store can't be used, with what to do about it
+pub fn unavailable_message(cli: &Cli) -> String {
+    match cli.store_type() {
+        StoreType::Qdrant => format!(
+            "Qdrant is not available at {}. Run 'know up' to start services.",
+            cli.qdrant_url
+        ),
+        StoreType::Local => {
+            "The local store is not available. Check that --store-path is writable.".to_string()
+        }
+        // The URL may carry a password, so it isn't repeated
+        StoreType::Postgres => "PostgreSQL is not available at --store-url. Check that the \
+            server is running and the URL is right."
+            .to_string(),
+    }
+}
+
+/// Fail if an existing collection holds vectors of another size than the
+/// embedding model produces, since they can't be stored or searched together
+pub fn check_vector_size(collection: &str, existing: usize, vector_size: usize) -> Result<()> {
+    if existing != vector_size {
+        anyhow::bail!(
+            "Collection '{}' holds {}-dimensional vectors, but the embedding model \
+            produces {} dimensions. Use the model the collection was built with, \
+            or migrate it with 'know reembed'.",
+            collection,
+            existing,
+            vector_size
+        );
+    }
+    Ok(())
+}
+
+/// Clean/delete a collection
+pub async fn clean(cli: &Cli, collection: &str) -> Result<()> {
+    let store = create_store(cli).await?;
+
+    if !store.is_available().await {
+        println!("{}", unavailable_message(cli));
+        return Ok(());
+    }
+
+    store.delete_collection(collection).await?;
+    println!("Collection '{}' deleted.", collection);
+
+    Ok(())
+}